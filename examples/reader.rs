@@ -4,30 +4,35 @@ use std::io::BufReader;
 fn main() -> anyhow::Result<()> {
     let file = File::open("assets/Alicia/Alicia_solid.pmx")?;
     let reader = pmx::Reader::new(BufReader::new(file))?;
-    println!("[name] {}", reader.name());
-    println!("[name EN] {}", reader.name_en());
-    println!("[comment]\n{}", reader.comment());
-    println!("[comment EN]\n{}", reader.comment_en());
+    println!("[name] {}", reader.name()?);
+    println!("[name EN] {}", reader.name_en()?);
+    println!("[comment]\n{}", reader.comment()?);
+    println!("[comment EN]\n{}", reader.comment_en()?);
     println!("[vertices] len = {}", reader.vertices().len());
     println!("[faces] len = {}", reader.faces().len());
     println!("[textures] len = {}", reader.textures().len());
     for texture in reader.textures() {
+        let texture = texture?;
         println!("{}", texture.to_string_lossy());
     }
     println!("[materials] len = {}", reader.materials().len());
     for material in reader.materials() {
+        let material = material?;
         println!("{}", material.name);
     }
     println!("[bones] len = {}", reader.bones().len());
     for bone in reader.bones() {
+        let bone = bone?;
         println!("{}", bone.name);
     }
     println!("[rigids] len = {}", reader.rigids().len());
     for rigid in reader.rigids() {
+        let rigid = rigid?;
         println!("{}", rigid.name);
     }
     println!("[joints] len = {}", reader.joints().len());
     for joint in reader.joints() {
+        let joint = joint?;
         println!("{}", joint.name);
     }
     Ok(())
@@ -0,0 +1,306 @@
+use super::*;
+use crate::math;
+
+impl Bezier {
+    pub fn is_linear(&self) -> bool {
+        self.p1[0] == self.p1[1] && self.p2[0] == self.p2[1]
+    }
+
+    // Control points are in 0..=127 and the curve is solved for x by bisection.
+    pub fn evaluate(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        if self.is_linear() {
+            return t;
+        }
+        let [x1, y1] = self.p1.map(|v| v as f32 / 127.0);
+        let [x2, y2] = self.p2.map(|v| v as f32 / 127.0);
+        let curve = |a: f32, b: f32, s: f32| {
+            let r = 1.0 - s;
+            3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+        };
+        let (mut lo, mut hi) = (0.0f32, 1.0f32);
+        let mut s = t;
+        for _ in 0..32 {
+            let x = curve(x1, x2, s);
+            if (x - t).abs() < 1e-6 {
+                break;
+            }
+            if x < t {
+                lo = s;
+            } else {
+                hi = s;
+            }
+            s = (lo + hi) * 0.5;
+        }
+        curve(y1, y2, s)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Camera {
+    pub distance: f32,
+    pub position: [f32; 3],
+    pub rotation: [f32; 3],
+    pub view_angle: f32,
+    pub perspective: bool,
+}
+
+// Returns the keyframes around `frame` and the blend factor between them.
+fn neighbors<T>(keyframes: &[T], frame: f32, key: impl Fn(&T) -> u32) -> Option<(&T, &T, f32)> {
+    let next = keyframes.partition_point(|k| key(k) as f32 <= frame);
+    let (a, b) = match next {
+        0 => (keyframes.first()?, keyframes.first()?),
+        n if n == keyframes.len() => (&keyframes[n - 1], &keyframes[n - 1]),
+        n => (&keyframes[n - 1], &keyframes[n]),
+    };
+    let (fa, fb) = (key(a) as f32, key(b) as f32);
+    let t = if fb > fa {
+        (frame - fa) / (fb - fa)
+    } else {
+        0.0
+    };
+    Some((a, b, t.clamp(0.0, 1.0)))
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    bones: Vec<Vec<BoneKeyframe>>,
+    morphs: Vec<Vec<MorphKeyframe>>,
+    cameras: Vec<CameraKeyframe>,
+    unresolved: Vec<String>,
+    last_frame: u32,
+}
+
+impl Animation {
+    pub fn new(motion: &Motion, names: &NameIndex, bones_len: usize, morphs_len: usize) -> Self {
+        let mut bones = vec![vec![]; bones_len];
+        let mut morphs = vec![vec![]; morphs_len];
+        let mut unresolved = vec![];
+        for k in &motion.bones {
+            match names.bone(&k.bone).and_then(|i| bones.get_mut(i)) {
+                Some(track) => track.push(k.clone()),
+                None => unresolved.push(k.bone.clone()),
+            }
+        }
+        for k in &motion.morphs {
+            match names.morph(&k.morph).and_then(|i| morphs.get_mut(i)) {
+                Some(track) => track.push(k.clone()),
+                None => unresolved.push(k.morph.clone()),
+            }
+        }
+        unresolved.sort();
+        unresolved.dedup();
+        for track in &mut bones {
+            track.sort_by_key(|k: &BoneKeyframe| k.frame);
+        }
+        for track in &mut morphs {
+            track.sort_by_key(|k: &MorphKeyframe| k.frame);
+        }
+        let mut cameras = motion.cameras.clone();
+        cameras.sort_by_key(|k| k.frame);
+        let last_frame = motion
+            .bones
+            .iter()
+            .map(|k| k.frame)
+            .chain(motion.morphs.iter().map(|k| k.frame))
+            .chain(motion.cameras.iter().map(|k| k.frame))
+            .max()
+            .unwrap_or(0);
+        Self {
+            bones,
+            morphs,
+            cameras,
+            unresolved,
+            last_frame,
+        }
+    }
+
+    pub fn from_reader(motion: &Motion, reader: &Reader) -> Result<Self, Error> {
+        let names = NameIndex::from_reader(reader)?;
+        Ok(Self::new(
+            motion,
+            &names,
+            reader.bones().len(),
+            reader.morphs().len(),
+        ))
+    }
+
+    pub fn from_model(motion: &Motion, model: &Model) -> Self {
+        Self::new(
+            motion,
+            &NameIndex::from_model(model),
+            model.bones.len(),
+            model.morphs.len(),
+        )
+    }
+
+    pub fn unresolved(&self) -> &[String] {
+        &self.unresolved
+    }
+
+    pub fn last_frame(&self) -> u32 {
+        self.last_frame
+    }
+
+    pub fn sample(&self, frame: f32) -> Pose {
+        let mut pose = Pose::new(self.bones.len(), self.morphs.len());
+        self.sample_into(frame, &mut pose);
+        pose
+    }
+
+    pub fn sample_into(&self, frame: f32, pose: &mut Pose) {
+        pose.bones.resize(self.bones.len(), BoneTransform::IDENTITY);
+        pose.morphs.resize(self.morphs.len(), 0.0);
+        for (transform, track) in pose.bones.iter_mut().zip(&self.bones) {
+            *transform = sample_bone(track, frame);
+        }
+        for (weight, track) in pose.morphs.iter_mut().zip(&self.morphs) {
+            *weight = neighbors(track, frame, |k| k.frame)
+                .map_or(0.0, |(a, b, t)| lerp(a.weight, b.weight, t));
+        }
+    }
+
+    pub fn camera(&self, frame: f32) -> Option<Camera> {
+        let (a, b, t) = neighbors(&self.cameras, frame, |k| k.frame)?;
+        // Keyframes on adjacent frames are treated as a cut.
+        let t = if b.frame == a.frame + 1 { 0.0 } else { t };
+        let curves = &b.interpolation;
+        let [tx, ty, tz] = [curves.x, curves.y, curves.z].map(|c| c.evaluate(t));
+        let tr = curves.rotation.evaluate(t);
+        let ta = curves.view_angle.evaluate(t);
+        Some(Camera {
+            distance: lerp(a.distance, b.distance, curves.distance.evaluate(t)),
+            position: [
+                lerp(a.position[0], b.position[0], tx),
+                lerp(a.position[1], b.position[1], ty),
+                lerp(a.position[2], b.position[2], tz),
+            ],
+            rotation: std::array::from_fn(|i| lerp(a.rotation[i], b.rotation[i], tr)),
+            view_angle: lerp(a.view_angle as f32, b.view_angle as f32, ta),
+            perspective: a.perspective,
+        })
+    }
+}
+
+fn sample_bone(track: &[BoneKeyframe], frame: f32) -> BoneTransform {
+    let Some((a, b, t)) = neighbors(track, frame, |k| k.frame) else {
+        return BoneTransform::IDENTITY;
+    };
+    // The curves stored on a keyframe describe the segment that ends at it.
+    let curves = &b.interpolation;
+    let [tx, ty, tz] = [curves.x, curves.y, curves.z].map(|c| c.evaluate(t));
+    BoneTransform {
+        translation: [
+            lerp(a.translation[0], b.translation[0], tx),
+            lerp(a.translation[1], b.translation[1], ty),
+            lerp(a.translation[2], b.translation[2], tz),
+        ],
+        rotation: math::slerp(a.rotation, b.rotation, curves.rotation.evaluate(t)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bone_keyframe(bone: &str, frame: u32, translation: [f32; 3]) -> BoneKeyframe {
+        BoneKeyframe {
+            bone: bone.to_string(),
+            frame,
+            translation,
+            rotation: math::QUAT_IDENTITY,
+            interpolation: BoneInterpolation::default(),
+        }
+    }
+
+    #[test]
+    fn bezier() {
+        let linear = Bezier::default();
+        assert!(linear.evaluate(0.25) == 0.25);
+        let ease = Bezier {
+            p1: [127, 0],
+            p2: [0, 127],
+        };
+        assert!(ease.evaluate(0.0).abs() < 1e-5);
+        assert!((ease.evaluate(0.5) - 0.5).abs() < 1e-4);
+        assert!((ease.evaluate(1.0) - 1.0).abs() < 1e-5);
+        assert!(ease.evaluate(0.1) < 0.1);
+        assert!(ease.evaluate(0.9) > 0.9);
+    }
+
+    #[test]
+    fn sample() {
+        let mut motion = Motion::default();
+        let mut k = bone_keyframe("b", 10, [10.0, 0.0, 0.0]);
+        k.rotation = math::quat_from_axis_angle([0.0, 1.0, 0.0], 1.0);
+        k.interpolation.y = Bezier {
+            p1: [127, 0],
+            p2: [127, 0],
+        };
+        motion.bones.push(k);
+        motion.bones.push(bone_keyframe("b", 0, [0.0; 3]));
+        motion.bones.push(bone_keyframe("missing", 0, [0.0; 3]));
+        motion.morphs.push(MorphKeyframe {
+            morph: "m".to_string(),
+            frame: 4,
+            weight: 1.0,
+        });
+        let names = NameIndex::new(["a", "b"], ["m"]);
+        let animation = Animation::new(&motion, &names, 2, 1);
+        assert!(animation.unresolved() == ["missing"]);
+        assert!(animation.last_frame() == 10);
+        let pose = animation.sample(5.0);
+        assert!(pose.bones[0].is_identity());
+        assert!(pose.bones[1].translation[0] == 5.0);
+        let half = math::quat_from_axis_angle([0.0, 1.0, 0.0], 0.5);
+        assert!((0..4).all(|i| (pose.bones[1].rotation[i] - half[i]).abs() < 1e-5));
+        assert!(pose.morphs[0] == 1.0);
+        assert!(animation.sample(0.0).morphs[0] == 1.0);
+        assert!(animation.sample(20.0).bones[1].translation == [10.0, 0.0, 0.0]);
+        assert!(animation.sample(-1.0).bones[1].translation == [0.0; 3]);
+    }
+
+    #[test]
+    fn camera() {
+        let keyframe = |frame, distance| CameraKeyframe {
+            frame,
+            distance,
+            position: [0.0, 10.0, 0.0],
+            rotation: [0.0; 3],
+            interpolation: CameraInterpolation::default(),
+            view_angle: 30,
+            perspective: true,
+        };
+        let motion = Motion {
+            cameras: vec![keyframe(0, -10.0), keyframe(10, -30.0), keyframe(11, 0.0)],
+            ..Default::default()
+        };
+        let animation = Animation::new(&motion, &NameIndex::default(), 0, 0);
+        assert!(animation.camera(5.0).unwrap().distance == -20.0);
+        assert!(animation.camera(10.5).unwrap().distance == -30.0);
+        assert!(animation.camera(12.0).unwrap().view_angle == 30.0);
+        let empty = Animation::new(&Motion::default(), &NameIndex::default(), 0, 0);
+        assert!(empty.camera(0.0).is_none());
+    }
+
+    #[test]
+    fn reader() {
+        let reader =
+            Reader::from_bytes(include_bytes!("../assets/Alicia/Alicia_solid.pmx")).unwrap();
+        let bone = reader.bone(3).unwrap();
+        let motion = Motion {
+            bones: vec![bone_keyframe(&bone.name, 0, [1.0, 2.0, 3.0])],
+            ..Default::default()
+        };
+        let animation = Animation::from_reader(&motion, &reader).unwrap();
+        assert!(animation.unresolved().is_empty());
+        let pose = animation.sample(0.0);
+        assert!(pose.bones.len() == reader.bones().len());
+        assert!(pose.morphs.len() == reader.morphs().len());
+        assert!(pose.bones[3].translation == [1.0, 2.0, 3.0]);
+    }
+}
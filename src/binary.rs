@@ -0,0 +1,111 @@
+use super::*;
+use std::io::{Cursor, Read};
+
+pub(crate) fn decode_sjis(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let (s, _, _) = encoding_rs::SHIFT_JIS.decode(&bytes[..len]);
+    s.into_owned()
+}
+
+pub(crate) fn truncate_sjis(s: &str, len: usize) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut buffer = [0u8; 4];
+    for c in s.chars() {
+        let (encoded, _, _) = encoding_rs::SHIFT_JIS.encode(c.encode_utf8(&mut buffer));
+        if bytes.len() + encoded.len() > len {
+            break;
+        }
+        bytes.extend_from_slice(&encoded);
+    }
+    bytes
+}
+
+pub(crate) fn encode_sjis<const N: usize>(s: &str) -> Result<[u8; N], Error> {
+    let (_, _, unmappable) = encoding_rs::SHIFT_JIS.encode(s);
+    if unmappable {
+        return Err(Error::invalid_data(format!(
+            "{s} is not representable in Shift-JIS"
+        )));
+    }
+    let bytes = truncate_sjis(s, N);
+    let mut buffer = [0u8; N];
+    buffer[..bytes.len()].copy_from_slice(&bytes);
+    Ok(buffer)
+}
+
+pub(crate) fn in_elements<T>(
+    section: Section,
+    len: usize,
+    mut f: impl FnMut(usize) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    (0..len)
+        .map(|i| f(i).map_err(|e| e.in_element(section, Some(i))))
+        .collect()
+}
+
+pub(crate) struct BinaryCursor<'a> {
+    reader: Cursor<&'a [u8]>,
+    last: u64,
+}
+
+impl<'a> BinaryCursor<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self {
+            reader: Cursor::new(data),
+            last: 0,
+        }
+    }
+
+    pub(crate) fn invalid(&self, msg: &str, value: impl Into<i64>) -> Error {
+        Error::invalid_value(msg, self.last, value.into())
+    }
+
+    pub(crate) fn is_end(&self) -> bool {
+        self.reader.position() >= self.reader.get_ref().len() as u64
+    }
+
+    pub(crate) fn read_bin<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buffer = [0u8; N];
+        self.last = self.reader.position();
+        if self.reader.read_exact(&mut buffer).is_err() {
+            return Err(Error::unexpected_eof(self.last));
+        }
+        Ok(buffer)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bin::<1>()?[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_bin::<2>()?))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_bin::<4>()?))
+    }
+
+    pub(crate) fn read_f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_le_bytes(self.read_bin::<4>()?))
+    }
+
+    pub(crate) fn read_vec<const N: usize>(&mut self) -> Result<[f32; N], Error> {
+        let mut buffer = [0.0f32; N];
+        for v in &mut buffer {
+            *v = self.read_f32()?;
+        }
+        Ok(buffer)
+    }
+
+    pub(crate) fn read_sjis<const N: usize>(&mut self) -> Result<String, Error> {
+        Ok(decode_sjis(&self.read_bin::<N>()?))
+    }
+
+    pub(crate) fn read_len(&mut self, len: usize, element_size: usize) -> Result<usize, Error> {
+        let rest = self.reader.get_ref().len() as u64 - self.reader.position();
+        if (len as u64).saturating_mul(element_size as u64) > rest {
+            return Err(self.invalid("element count", len as i64));
+        }
+        Ok(len)
+    }
+}
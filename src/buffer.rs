@@ -0,0 +1,400 @@
+use super::*;
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+#[repr(C)]
+pub struct GpuVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub bone_indices: [u32; 4],
+    pub bone_weights: [f32; 4],
+}
+
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for GpuVertex {}
+
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for GpuVertex {}
+
+impl GpuVertex {
+    pub fn new(vertex: &Vertex) -> Self {
+        let (bone_indices, bone_weights) = vertex.weight.influences();
+        Self {
+            position: vertex.position,
+            normal: vertex.normal,
+            uv: vertex.uv,
+            bone_indices,
+            bone_weights,
+        }
+    }
+}
+
+impl Weight {
+    pub fn influences(&self) -> ([u32; 4], [f32; 4]) {
+        let (bones, weights) = match self {
+            Weight::Bdef1(w) => ([w.bone, None, None, None], [1.0, 0.0, 0.0, 0.0]),
+            Weight::Bdef2(w) => (
+                [w.bones[0], w.bones[1], None, None],
+                [w.weight, 1.0 - w.weight, 0.0, 0.0],
+            ),
+            Weight::Sdef(w) => (
+                [w.bones[0], w.bones[1], None, None],
+                [w.weight, 1.0 - w.weight, 0.0, 0.0],
+            ),
+            Weight::Bdef4(w) => (w.bones, w.weights),
+            Weight::Qdef(w) => (w.bones, w.weights),
+        };
+        let mut indices = [0; 4];
+        let mut values = [0.0; 4];
+        for i in 0..4 {
+            if let Some(bone) = bones[i] {
+                indices[i] = bone as u32;
+                values[i] = weights[i].max(0.0);
+            }
+        }
+        let sum = values.iter().sum::<f32>();
+        if sum > 0.0 {
+            values.iter_mut().for_each(|v| *v /= sum);
+        }
+        (indices, values)
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct VertexBuffers {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub extended_uvs: Vec<Vec<[f32; 4]>>,
+    pub bone_indices: Vec<[u32; 4]>,
+    pub bone_weights: Vec<[f32; 4]>,
+}
+
+impl VertexBuffers {
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.uvs.clear();
+        self.extended_uvs.iter_mut().for_each(|uvs| uvs.clear());
+        self.bone_indices.clear();
+        self.bone_weights.clear();
+    }
+
+    pub fn push(&mut self, vertex: &Vertex) {
+        self.positions.push(vertex.position);
+        self.normals.push(vertex.normal);
+        self.uvs.push(vertex.uv);
+        if self.extended_uvs.len() < vertex.extended_uv.len() {
+            let len = self.positions.len() - 1;
+            self.extended_uvs
+                .resize(vertex.extended_uv.len(), vec![[0.0; 4]; len]);
+        }
+        for (i, uvs) in self.extended_uvs.iter_mut().enumerate() {
+            uvs.push(vertex.extended_uv.get(i).copied().unwrap_or_default());
+        }
+        let (indices, weights) = vertex.weight.influences();
+        self.bone_indices.push(indices);
+        self.bone_weights.push(weights);
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum IndexBuffer {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl IndexBuffer {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(v) => v.len(),
+            Self::U32(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn check_len(expected: usize, len: usize) -> Result<(), Error> {
+    if expected != len {
+        return Err(Error::invalid_data(format!(
+            "buffer length {len} does not match {expected}"
+        )));
+    }
+    Ok(())
+}
+
+fn fill_indices_u16(
+    faces: impl ExactSizeIterator<Item = Result<usize, Error>>,
+    dst: &mut [u16],
+) -> Result<(), Error> {
+    check_len(faces.len(), dst.len())?;
+    for (dst, index) in dst.iter_mut().zip(faces) {
+        *dst = u16::try_from(index?).map_err(|_| Error::invalid_data("u16 index"))?;
+    }
+    Ok(())
+}
+
+fn fill_indices_u32(
+    faces: impl ExactSizeIterator<Item = Result<usize, Error>>,
+    dst: &mut [u32],
+) -> Result<(), Error> {
+    check_len(faces.len(), dst.len())?;
+    for (dst, index) in dst.iter_mut().zip(faces) {
+        *dst = u32::try_from(index?).map_err(|_| Error::invalid_data("u32 index"))?;
+    }
+    Ok(())
+}
+
+fn index_buffer(
+    vertices_len: usize,
+    faces: impl ExactSizeIterator<Item = Result<usize, Error>>,
+) -> Result<IndexBuffer, Error> {
+    if vertices_len <= u16::MAX as usize + 1 {
+        let mut buffer = vec![0; faces.len()];
+        fill_indices_u16(faces, &mut buffer)?;
+        Ok(IndexBuffer::U16(buffer))
+    } else {
+        let mut buffer = vec![0; faces.len()];
+        fill_indices_u32(faces, &mut buffer)?;
+        Ok(IndexBuffer::U32(buffer))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Submesh {
+    pub material: usize,
+    pub range: Range<usize>,
+    pub vertices: Vec<usize>,
+}
+
+fn material_ranges(
+    index_counts: impl Iterator<Item = Result<u32, Error>>,
+    faces_len: usize,
+) -> Result<Vec<Range<usize>>, Error> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    for (i, count) in index_counts.enumerate() {
+        let count = count?;
+        if count % 3 != 0 {
+            return Err(
+                Error::invalid_data(format!("index count {count} is not a multiple of 3"))
+                    .in_element(Section::Materials, Some(i)),
+            );
+        }
+        let end = start + count as usize;
+        ranges.push(start..end);
+        start = end;
+    }
+    if start != faces_len {
+        return Err(Error::invalid_data(format!(
+            "sum of index counts {start} does not match {faces_len} face indices"
+        ))
+        .in_element(Section::Materials, None));
+    }
+    Ok(ranges)
+}
+
+fn submeshes(ranges: Vec<Range<usize>>, faces: &[usize]) -> Vec<Submesh> {
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(material, range)| {
+            let mut vertices = faces[range.clone()].to_vec();
+            vertices.sort_unstable();
+            vertices.dedup();
+            Submesh {
+                material,
+                range,
+                vertices,
+            }
+        })
+        .collect()
+}
+
+impl Reader<'_> {
+    pub fn gpu_vertices(&self) -> Result<Vec<GpuVertex>, Error> {
+        let mut buffer = vec![GpuVertex::default(); self.vertices().len()];
+        self.fill_gpu_vertices(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    pub fn fill_gpu_vertices(&self, dst: &mut [GpuVertex]) -> Result<(), Error> {
+        let vertices = self.vertices();
+        check_len(vertices.len(), dst.len())?;
+        for (dst, vertex) in dst.iter_mut().zip(vertices) {
+            *dst = GpuVertex::new(&vertex?);
+        }
+        Ok(())
+    }
+
+    pub fn vertex_buffers(&self) -> Result<VertexBuffers, Error> {
+        let mut buffers = VertexBuffers::default();
+        self.fill_vertex_buffers(&mut buffers)?;
+        Ok(buffers)
+    }
+
+    pub fn fill_vertex_buffers(&self, buffers: &mut VertexBuffers) -> Result<(), Error> {
+        buffers.clear();
+        buffers
+            .extended_uvs
+            .resize(self.header().extended_uv as usize, vec![]);
+        for vertex in self.vertices() {
+            buffers.push(&vertex?);
+        }
+        Ok(())
+    }
+
+    pub fn index_buffer(&self) -> Result<IndexBuffer, Error> {
+        index_buffer(self.vertices().len(), self.faces())
+    }
+
+    pub fn fill_indices_u16(&self, dst: &mut [u16]) -> Result<(), Error> {
+        fill_indices_u16(self.faces(), dst)
+    }
+
+    pub fn fill_indices_u32(&self, dst: &mut [u32]) -> Result<(), Error> {
+        fill_indices_u32(self.faces(), dst)
+    }
+
+    pub fn material_ranges(&self) -> Result<Vec<Range<usize>>, Error> {
+        material_ranges(
+            self.materials().map(|m| m.map(|m| m.index_count)),
+            self.faces().len(),
+        )
+    }
+
+    pub fn submeshes(&self) -> Result<Vec<Submesh>, Error> {
+        let ranges = self.material_ranges()?;
+        let faces = self.faces().collect::<Result<Vec<_>, _>>()?;
+        Ok(submeshes(ranges, &faces))
+    }
+}
+
+impl Model {
+    pub fn gpu_vertices(&self) -> Vec<GpuVertex> {
+        self.vertices.iter().map(GpuVertex::new).collect()
+    }
+
+    pub fn vertex_buffers(&self) -> VertexBuffers {
+        let mut buffers = VertexBuffers {
+            extended_uvs: vec![vec![]; self.header.extended_uv as usize],
+            ..Default::default()
+        };
+        for vertex in &self.vertices {
+            buffers.push(vertex);
+        }
+        buffers
+    }
+
+    pub fn index_buffer(&self) -> Result<IndexBuffer, Error> {
+        index_buffer(self.vertices.len(), self.faces.iter().map(|&i| Ok(i)))
+    }
+
+    pub fn material_ranges(&self) -> Result<Vec<Range<usize>>, Error> {
+        material_ranges(
+            self.materials.iter().map(|m| Ok(m.index_count)),
+            self.faces.len(),
+        )
+    }
+
+    pub fn submeshes(&self) -> Result<Vec<Submesh>, Error> {
+        Ok(submeshes(self.material_ranges()?, &self.faces))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_reader() -> Reader<'static> {
+        Reader::from_bytes(include_bytes!("../assets/Alicia/Alicia_solid.pmx")).unwrap()
+    }
+
+    #[test]
+    fn influences() {
+        let weight = Weight::Bdef2(Bdef2 {
+            bones: [Some(3), Some(5)],
+            weight: 0.25,
+        });
+        assert!(weight.influences() == ([3, 5, 0, 0], [0.25, 0.75, 0.0, 0.0]));
+        let weight = Weight::Bdef4(Bdef4 {
+            bones: [Some(1), None, Some(2), Some(4)],
+            weights: [1.0, 0.5, 2.0, 1.0],
+        });
+        assert!(weight.influences() == ([1, 0, 2, 4], [0.25, 0.0, 0.5, 0.25]));
+        let weight = Weight::Bdef1(Bdef1 { bone: None });
+        assert!(weight.influences() == ([0; 4], [0.0; 4]));
+    }
+
+    #[test]
+    fn vertices() {
+        let reader = new_reader();
+        let vertices = reader.gpu_vertices().unwrap();
+        assert!(vertices.len() == 22311);
+        let last = vertices.last().unwrap();
+        assert!(last.bone_indices[0] == 35);
+        assert!(last.bone_weights == [1.0, 0.0, 0.0, 0.0]);
+        let buffers = reader.vertex_buffers().unwrap();
+        assert!(buffers.len() == 22311);
+        assert!(buffers.extended_uvs.len() == reader.header().extended_uv as usize);
+        assert!(buffers.positions[100] == vertices[100].position);
+        let model = Model::from_reader(&reader).unwrap();
+        assert!(model.gpu_vertices() == vertices);
+        assert!(reader
+            .fill_gpu_vertices(&mut vertices[..10].to_vec())
+            .is_err());
+    }
+
+    #[test]
+    fn indices() {
+        let reader = new_reader();
+        let IndexBuffer::U16(indices) = reader.index_buffer().unwrap() else {
+            panic!();
+        };
+        assert!(indices.len() == 95598);
+        assert!(*indices.last().unwrap() == 4382);
+        let mut buffer = vec![0u32; indices.len()];
+        reader.fill_indices_u32(&mut buffer).unwrap();
+        assert!(buffer.iter().zip(&indices).all(|(&a, &b)| a == b as u32));
+        let model = Model::from_reader(&reader).unwrap();
+        assert!(model.index_buffer().unwrap() == IndexBuffer::U16(indices));
+    }
+
+    #[test]
+    fn submeshes() {
+        let reader = new_reader();
+        let submeshes = reader.submeshes().unwrap();
+        assert!(submeshes.len() == 22);
+        assert!(submeshes[0].range.start == 0);
+        assert!(submeshes.last().unwrap().range.end == 95598);
+        assert!(submeshes
+            .windows(2)
+            .all(|s| s[0].range.end == s[1].range.start));
+        assert!(submeshes[0].vertices.windows(2).all(|v| v[0] < v[1]));
+        let mut model = Model::from_reader(&reader).unwrap();
+        assert!(model.submeshes().unwrap() == submeshes);
+        model.materials[3].index_count += 3;
+        let e = model.material_ranges().unwrap_err();
+        assert!(e.info().unwrap().section == Some(Section::Materials));
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn pod() {
+        let vertices = new_reader().gpu_vertices().unwrap();
+        let bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        assert!(bytes.len() == vertices.len() * 64);
+    }
+}
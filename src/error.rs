@@ -0,0 +1,163 @@
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Section {
+    Info,
+    Vertices,
+    Faces,
+    Textures,
+    Materials,
+    Bones,
+    Morphs,
+    DisplayGroups,
+    Rigids,
+    Joints,
+    SoftBodies,
+    BoneKeyframes,
+    MorphKeyframes,
+    CameraKeyframes,
+    LightKeyframes,
+    ShadowKeyframes,
+    IkKeyframes,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Info => "info",
+            Self::Vertices => "vertices",
+            Self::Faces => "faces",
+            Self::Textures => "textures",
+            Self::Materials => "materials",
+            Self::Bones => "bones",
+            Self::Morphs => "morphs",
+            Self::DisplayGroups => "display groups",
+            Self::Rigids => "rigids",
+            Self::Joints => "joints",
+            Self::SoftBodies => "soft bodies",
+            Self::BoneKeyframes => "bone keyframes",
+            Self::MorphKeyframes => "morph keyframes",
+            Self::CameraKeyframes => "camera keyframes",
+            Self::LightKeyframes => "light keyframes",
+            Self::ShadowKeyframes => "shadow keyframes",
+            Self::IkKeyframes => "ik keyframes",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ErrorInfo {
+    pub message: String,
+    pub section: Option<Section>,
+    pub index: Option<usize>,
+    pub offset: Option<u64>,
+    pub value: Option<i64>,
+}
+
+impl ErrorInfo {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            section: None,
+            index: None,
+            offset: None,
+            value: None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        let mut context = vec![];
+        match (self.section, self.index) {
+            (Some(section), Some(index)) => context.push(format!("{section}[{index}]")),
+            (Some(section), None) => context.push(section.to_string()),
+            _ => {}
+        }
+        if let Some(offset) = self.offset {
+            context.push(format!("offset {offset:#x}"));
+        }
+        if let Some(value) = self.value {
+            context.push(format!("value {value}"));
+        }
+        if !context.is_empty() {
+            write!(f, " ({})", context.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unsupported version")]
+    UnsupportedVersion,
+    #[error("invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("invalid data: {0}")]
+    InvalidData(ErrorInfo),
+    #[error("unexpected end of data: {0}")]
+    UnexpectedEof(ErrorInfo),
+    #[error("index out of range: {section}[{index}] (len {len})")]
+    OutOfRange {
+        section: Section,
+        index: usize,
+        len: usize,
+    },
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+}
+
+impl Error {
+    pub(crate) fn invalid_header(msg: impl Into<String>) -> Self {
+        Self::InvalidHeader(msg.into())
+    }
+
+    pub(crate) fn invalid_data(msg: impl Into<String>) -> Self {
+        Self::InvalidData(ErrorInfo::new(msg))
+    }
+
+    pub(crate) fn unexpected_eof(offset: u64) -> Self {
+        let mut info = ErrorInfo::new("read past the end");
+        info.offset = Some(offset);
+        Self::UnexpectedEof(info)
+    }
+
+    pub(crate) fn invalid_value(msg: impl Into<String>, offset: u64, value: i64) -> Self {
+        let mut info = ErrorInfo::new(msg);
+        info.offset = Some(offset);
+        info.value = Some(value);
+        Self::InvalidData(info)
+    }
+
+    pub(crate) fn in_element(mut self, section: Section, index: Option<usize>) -> Self {
+        if let Some(info) = self.info_mut() {
+            if info.section.is_none() {
+                info.section = Some(section);
+                info.index = index;
+            }
+        }
+        self
+    }
+
+    pub fn info(&self) -> Option<&ErrorInfo> {
+        match self {
+            Self::InvalidData(info) | Self::UnexpectedEof(info) => Some(info),
+            _ => None,
+        }
+    }
+
+    fn info_mut(&mut self) -> Option<&mut ErrorInfo> {
+        match self {
+            Self::InvalidData(info) | Self::UnexpectedEof(info) => Some(info),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
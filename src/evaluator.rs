@@ -0,0 +1,768 @@
+use super::*;
+use crate::math::{self, Mat4, Quat, Vec3};
+use std::f32::consts::PI;
+
+#[derive(Clone, Debug)]
+struct BoneState {
+    translation: Vec3,
+    rotation: Quat,
+    ik_rotation: Quat,
+    append_translation: Vec3,
+    append_rotation: Quat,
+    local: Mat4,
+    world: Mat4,
+}
+
+impl Default for BoneState {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: math::QUAT_IDENTITY,
+            ik_rotation: math::QUAT_IDENTITY,
+            append_translation: [0.0; 3],
+            append_rotation: math::QUAT_IDENTITY,
+            local: math::IDENTITY,
+            world: math::IDENTITY,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PoseEvaluator {
+    skeleton: Skeleton,
+    offsets: Vec<Vec3>,
+    inverse_binds: Vec<Mat4>,
+    states: Vec<BoneState>,
+    ik_enabled: Vec<bool>,
+}
+
+impl PoseEvaluator {
+    pub fn new(skeleton: Skeleton) -> Self {
+        let offsets = (0..skeleton.len())
+            .map(|i| skeleton.local_offset(i))
+            .collect();
+        let inverse_binds = skeleton.inverse_bind_matrices();
+        let states = vec![BoneState::default(); skeleton.len()];
+        let ik_enabled = vec![true; skeleton.len()];
+        let mut this = Self {
+            skeleton,
+            offsets,
+            inverse_binds,
+            states,
+            ik_enabled,
+        };
+        this.evaluate(&Pose::default());
+        this
+    }
+
+    pub fn skeleton(&self) -> &Skeleton {
+        &self.skeleton
+    }
+
+    pub fn evaluate(&mut self, pose: &Pose) {
+        self.set_pose(pose);
+        self.update_before_physics();
+        self.update_after_physics();
+    }
+
+    pub fn set_pose(&mut self, pose: &Pose) {
+        for (i, state) in self.states.iter_mut().enumerate() {
+            let transform = pose.bones.get(i).copied().unwrap_or_default();
+            let bone = &self.skeleton.bones()[i];
+            state.translation = transform.translation;
+            state.rotation = match bone.fixed_pole {
+                Some(axis) => twist(transform.rotation, axis),
+                None => math::quat_normalize(transform.rotation),
+            };
+            state.ik_rotation = math::QUAT_IDENTITY;
+            state.append_translation = [0.0; 3];
+            state.append_rotation = math::QUAT_IDENTITY;
+        }
+    }
+
+    pub fn update_before_physics(&mut self) {
+        self.update(false);
+    }
+
+    pub fn update_after_physics(&mut self) {
+        self.update(true);
+    }
+
+    fn update(&mut self, after_physics: bool) {
+        let order = if after_physics {
+            self.skeleton.after_physics_order().to_vec()
+        } else {
+            self.skeleton.before_physics_order().to_vec()
+        };
+        for &i in &order {
+            self.update_local(i);
+        }
+        for &i in &order {
+            if self.is_pass_root(i, after_physics) {
+                self.update_world(i);
+            }
+        }
+        for &i in &order {
+            if self.skeleton.bones()[i].addition.is_some() {
+                self.update_append(i);
+                self.update_world(i);
+            }
+            if self.skeleton.bones()[i].ik.is_some() && self.ik_enabled[i] {
+                self.solve_ik(i);
+                self.update_world(i);
+            }
+        }
+        for &i in &order {
+            if self.is_pass_root(i, after_physics) {
+                self.update_world(i);
+            }
+        }
+    }
+
+    fn is_pass_root(&self, i: usize, after_physics: bool) -> bool {
+        match self.skeleton.parent(i) {
+            Some(parent) => self.skeleton.bones()[parent].after_physics != after_physics,
+            None => true,
+        }
+    }
+
+    fn update_local(&mut self, i: usize) {
+        let state = &mut self.states[i];
+        let translation = math::add(
+            self.offsets[i],
+            math::add(state.translation, state.append_translation),
+        );
+        let rotation = math::quat_mul(
+            math::quat_mul(state.ik_rotation, state.rotation),
+            state.append_rotation,
+        );
+        state.local = math::from_rotation_translation(rotation, translation);
+    }
+
+    fn update_world(&mut self, i: usize) {
+        let mut stack = vec![i];
+        while let Some(i) = stack.pop() {
+            let world = match self.skeleton.parent(i) {
+                Some(parent) => math::mul(&self.states[parent].world, &self.states[i].local),
+                None => self.states[i].local,
+            };
+            self.states[i].world = world;
+            stack.extend(self.skeleton.children(i));
+        }
+    }
+
+    // The source bone contributes its animated rotation and translation scaled
+    // by `ratio`. Local grants take only those, otherwise the source's own
+    // grant and IK rotation are included as well.
+    fn update_append(&mut self, i: usize) {
+        let Some(addition) = self.skeleton.bones()[i].addition.clone() else {
+            return;
+        };
+        let Some(source) = addition.bone.filter(|&s| s < self.states.len() && s != i) else {
+            return;
+        };
+        let source_state = self.states[source].clone();
+        if addition.rotation {
+            let rotation = if addition.local {
+                source_state.rotation
+            } else {
+                math::quat_mul(
+                    math::quat_mul(source_state.ik_rotation, source_state.rotation),
+                    source_state.append_rotation,
+                )
+            };
+            self.states[i].append_rotation =
+                math::slerp(math::QUAT_IDENTITY, rotation, addition.ratio);
+        }
+        if addition.translation {
+            let translation = if addition.local {
+                source_state.translation
+            } else {
+                math::add(source_state.translation, source_state.append_translation)
+            };
+            self.states[i].append_translation = math::scale(translation, addition.ratio);
+        }
+        self.update_local(i);
+    }
+
+    pub fn set_ik_enabled(&mut self, index: usize, enabled: bool) {
+        self.ik_enabled[index] = enabled;
+    }
+
+    pub fn is_ik_enabled(&self, index: usize) -> bool {
+        self.ik_enabled[index]
+    }
+
+    fn solve_ik(&mut self, i: usize) {
+        let Some(ik) = self.skeleton.bones()[i].ik.clone() else {
+            return;
+        };
+        let len = self.states.len();
+        let Some(target) = ik.target_bone.filter(|&t| t < len) else {
+            return;
+        };
+        let mut links = ik
+            .links
+            .iter()
+            .filter_map(|link| {
+                let bone = link.bone.filter(|&b| b < len && b != target)?;
+                Some(IkChain {
+                    bone,
+                    limit: link.limit.clone(),
+                    previous: [0.0; 3],
+                    plane_angle: 0.0,
+                })
+            })
+            .collect::<Vec<_>>();
+        for link in &links {
+            self.states[link.bone].ik_rotation = math::QUAT_IDENTITY;
+            self.update_local(link.bone);
+            self.update_world(link.bone);
+        }
+        let mut min_distance = f32::MAX;
+        let mut saved = vec![math::QUAT_IDENTITY; links.len()];
+        for iteration in 0..ik.loop_count {
+            self.solve_ik_links(i, target, ik.angle, iteration, &mut links);
+            let distance = math::length(math::sub(
+                self.world_position(target),
+                self.world_position(i),
+            ));
+            if distance < min_distance {
+                min_distance = distance;
+                for (saved, link) in saved.iter_mut().zip(&links) {
+                    *saved = self.states[link.bone].ik_rotation;
+                }
+            } else {
+                for (&saved, link) in saved.iter().zip(&links) {
+                    self.states[link.bone].ik_rotation = saved;
+                    self.update_local(link.bone);
+                    self.update_world(link.bone);
+                }
+                break;
+            }
+        }
+    }
+
+    fn solve_ik_links(
+        &mut self,
+        i: usize,
+        target: usize,
+        limit_angle: f32,
+        iteration: u32,
+        links: &mut [IkChain],
+    ) {
+        let ik_position = self.world_position(i);
+        for link in links.iter_mut() {
+            if let Some(axis) = link.plane_axis() {
+                self.solve_ik_plane(i, target, limit_angle, iteration, link, axis);
+                continue;
+            }
+            let world = self.states[link.bone].world;
+            let ik_vec = math::normalize(math::inverse_transform_point(&world, ik_position));
+            let target_vec = math::normalize(math::inverse_transform_point(
+                &world,
+                self.world_position(target),
+            ));
+            let angle = math::dot(target_vec, ik_vec).clamp(-1.0, 1.0).acos();
+            if angle.to_degrees() < 1.0e-3 {
+                continue;
+            }
+            let angle = angle.clamp(-limit_angle, limit_angle);
+            let axis = math::cross(target_vec, ik_vec);
+            if math::length(axis) == 0.0 {
+                continue;
+            }
+            let state = &self.states[link.bone];
+            let mut rotation = math::quat_mul(
+                math::quat_mul(state.ik_rotation, state.rotation),
+                math::quat_from_axis_angle(axis, angle),
+            );
+            if let Some(limit) = &link.limit {
+                let euler = decompose(&rotation, link.previous);
+                let clamped = [0, 1, 2].map(|j| {
+                    let v = euler[j].clamp(limit.lower[j], limit.upper[j]);
+                    (v - link.previous[j]).clamp(-limit_angle, limit_angle) + link.previous[j]
+                });
+                rotation = euler_to_quat(clamped);
+                link.previous = clamped;
+            }
+            let state = &mut self.states[link.bone];
+            state.ik_rotation = math::quat_mul(rotation, math::quat_conjugate(state.rotation));
+            self.update_local(link.bone);
+            self.update_world(link.bone);
+        }
+    }
+
+    // Links limited to a single axis (knees) are solved by rotating around
+    // that axis only, in whichever direction brings the target closer.
+    fn solve_ik_plane(
+        &mut self,
+        i: usize,
+        target: usize,
+        limit_angle: f32,
+        iteration: u32,
+        link: &mut IkChain,
+        axis: usize,
+    ) {
+        let Some(limit) = link.limit.clone() else {
+            return;
+        };
+        let mut rotate_axis = [0.0; 3];
+        rotate_axis[axis] = 1.0;
+        let world = self.states[link.bone].world;
+        let ik_vec = math::normalize(math::inverse_transform_point(
+            &world,
+            self.world_position(i),
+        ));
+        let target_vec = math::normalize(math::inverse_transform_point(
+            &world,
+            self.world_position(target),
+        ));
+        let angle = math::dot(target_vec, ik_vec)
+            .clamp(-1.0, 1.0)
+            .acos()
+            .clamp(-limit_angle, limit_angle);
+        let dot1 = math::dot(
+            math::quat_rotate(math::quat_from_axis_angle(rotate_axis, angle), target_vec),
+            ik_vec,
+        );
+        let dot2 = math::dot(
+            math::quat_rotate(math::quat_from_axis_angle(rotate_axis, -angle), target_vec),
+            ik_vec,
+        );
+        let mut new_angle = link.plane_angle + if dot1 > dot2 { angle } else { -angle };
+        let (lower, upper) = (limit.lower[axis], limit.upper[axis]);
+        if iteration == 0 && (new_angle < lower || new_angle > upper) {
+            if -new_angle > lower && -new_angle < upper {
+                new_angle = -new_angle;
+            } else {
+                let half = (lower + upper) * 0.5;
+                if (half - new_angle).abs() > (half + new_angle).abs() {
+                    new_angle = -new_angle;
+                }
+            }
+        }
+        new_angle = new_angle.clamp(lower, upper);
+        link.plane_angle = new_angle;
+        let state = &mut self.states[link.bone];
+        state.ik_rotation = math::quat_mul(
+            math::quat_from_axis_angle(rotate_axis, new_angle),
+            math::quat_conjugate(state.rotation),
+        );
+        self.update_local(link.bone);
+        self.update_world(link.bone);
+    }
+
+    pub fn world_matrices(&self) -> Vec<[[f32; 4]; 4]> {
+        self.states.iter().map(|s| s.world).collect()
+    }
+
+    pub fn world_matrix(&self, index: usize) -> [[f32; 4]; 4] {
+        self.states[index].world
+    }
+
+    pub fn set_world_matrix(&mut self, index: usize, world: [[f32; 4]; 4]) {
+        self.states[index].world = world;
+    }
+
+    // Overrides the world transforms of the given bones, e.g. from physics,
+    // and carries the change down to their descendants.
+    pub fn set_world_matrices(&mut self, bones: &[(usize, [[f32; 4]; 4])]) {
+        let mut overridden = vec![false; self.states.len()];
+        for &(i, world) in bones {
+            self.states[i].world = world;
+            overridden[i] = true;
+        }
+        for &(i, world) in bones {
+            self.states[i].local = match self.skeleton.parent(i) {
+                Some(parent) => math::mul(&math::inverse_rigid(&self.states[parent].world), &world),
+                None => world,
+            };
+        }
+        for &(i, _) in bones {
+            let mut parent = self.skeleton.parent(i);
+            while let Some(p) = parent.filter(|&p| !overridden[p]) {
+                parent = self.skeleton.parent(p);
+            }
+            if parent.is_none() {
+                self.update_world(i);
+            }
+        }
+    }
+
+    pub fn world_position(&self, index: usize) -> [f32; 3] {
+        math::transform_point(&self.states[index].world, [0.0; 3])
+    }
+
+    pub fn skinning_matrix(&self, index: usize) -> [[f32; 4]; 4] {
+        math::mul(&self.states[index].world, &self.inverse_binds[index])
+    }
+
+    pub fn skinning_matrices(&self) -> Vec<[[f32; 4]; 4]> {
+        (0..self.states.len())
+            .map(|i| self.skinning_matrix(i))
+            .collect()
+    }
+}
+
+struct IkChain {
+    bone: usize,
+    limit: Option<AngleLimit>,
+    previous: Vec3,
+    plane_angle: f32,
+}
+
+impl IkChain {
+    fn plane_axis(&self) -> Option<usize> {
+        let limit = self.limit.as_ref()?;
+        let free = (0..3)
+            .filter(|&j| limit.lower[j] != 0.0 || limit.upper[j] != 0.0)
+            .collect::<Vec<_>>();
+        match free[..] {
+            [axis] => Some(axis),
+            _ => None,
+        }
+    }
+}
+
+fn euler_to_quat(euler: Vec3) -> Quat {
+    math::quat_mul(
+        math::quat_mul(
+            math::quat_from_axis_angle([1.0, 0.0, 0.0], euler[0]),
+            math::quat_from_axis_angle([0.0, 1.0, 0.0], euler[1]),
+        ),
+        math::quat_from_axis_angle([0.0, 0.0, 1.0], euler[2]),
+    )
+}
+
+fn diff_angle(a: f32, b: f32) -> f32 {
+    let d = (a - b).rem_euclid(2.0 * PI);
+    if d > PI {
+        d - 2.0 * PI
+    } else {
+        d
+    }
+}
+
+// Decomposes into X * Y * Z Euler angles, picking the equivalent set closest
+// to the angles of the previous iteration.
+fn decompose(rotation: &Quat, previous: Vec3) -> Vec3 {
+    let m = math::from_rotation_translation(*rotation, [0.0; 3]);
+    let sy = m[2][0].clamp(-1.0, 1.0);
+    let y = sy.asin();
+    let base = if 1.0 - sy.abs() < 1.0e-6 {
+        if previous[0].sin().abs() < previous[2].sin().abs() {
+            [0.0, y, (m[1][2] * sy).atan2(m[1][1])]
+        } else {
+            [m[1][2].atan2(m[1][1]), y, 0.0]
+        }
+    } else {
+        [(-m[2][1]).atan2(m[2][2]), y, (-m[1][0]).atan2(m[0][0])]
+    };
+    let error = |e: &Vec3| -> f32 { (0..3).map(|j| diff_angle(e[j], previous[j]).abs()).sum() };
+    let mut r = base;
+    let mut min_error = error(&r);
+    for x in [base[0] + PI, base[0] - PI] {
+        for y in [PI - base[1], -PI - base[1]] {
+            for z in [base[2] + PI, base[2] - PI] {
+                let candidate = [x, y, z];
+                let e = error(&candidate);
+                if e < min_error {
+                    min_error = e;
+                    r = candidate;
+                }
+            }
+        }
+    }
+    r
+}
+
+fn twist(rotation: Quat, axis: Vec3) -> Quat {
+    let axis = math::normalize(axis);
+    let p = math::scale(
+        axis,
+        math::dot([rotation[0], rotation[1], rotation[2]], axis),
+    );
+    math::quat_normalize([p[0], p[1], p[2], rotation[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bone(name: &str, position: Vec3, parent: Option<usize>) -> Bone {
+        Bone {
+            name: name.to_string(),
+            name_en: String::new(),
+            position,
+            parent,
+            deform_hierarchy: 0,
+            connected_to: ConnectTo::Bone(None),
+            rotatable: true,
+            translatable: false,
+            visibility: true,
+            operable: true,
+            ik: None,
+            addition: None,
+            after_physics: false,
+            fixed_pole: None,
+            local_pole: None,
+            external_parent: None,
+        }
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5)
+    }
+
+    fn rotation_z(angle: f32) -> Quat {
+        math::quat_from_axis_angle([0.0, 0.0, 1.0], angle)
+    }
+
+    #[test]
+    fn rest_pose() {
+        let reader =
+            Reader::from_bytes(include_bytes!("../assets/Alicia/Alicia_solid.pmx")).unwrap();
+        let skeleton = Skeleton::from_reader(&reader).unwrap();
+        let mut evaluator = PoseEvaluator::new(skeleton.clone());
+        (0..skeleton.len()).for_each(|i| evaluator.set_ik_enabled(i, false));
+        evaluator.evaluate(&Pose::new(skeleton.len(), 0));
+        for i in 0..skeleton.len() {
+            assert!(close(
+                evaluator.world_position(i),
+                skeleton.bones()[i].position
+            ));
+            let m = evaluator.skinning_matrix(i);
+            assert!(close(
+                math::transform_point(&m, [1.0, 2.0, 3.0]),
+                [1.0, 2.0, 3.0]
+            ));
+        }
+    }
+
+    #[test]
+    fn hierarchy() {
+        let bones = vec![
+            bone("a", [0.0, 0.0, 0.0], None),
+            bone("b", [1.0, 0.0, 0.0], Some(0)),
+            bone("c", [2.0, 0.0, 0.0], Some(1)),
+        ];
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(3, 0);
+        pose.bones[0].rotation = rotation_z(std::f32::consts::FRAC_PI_2);
+        pose.bones[1].translation = [0.0, 0.0, 1.0];
+        evaluator.evaluate(&pose);
+        assert!(close(evaluator.world_position(1), [0.0, 1.0, 1.0]));
+        assert!(close(evaluator.world_position(2), [0.0, 2.0, 1.0]));
+    }
+
+    #[test]
+    fn addition() {
+        let mut bones = vec![
+            bone("a", [0.0, 0.0, 0.0], None),
+            bone("b", [1.0, 0.0, 0.0], None),
+            bone("c", [0.0, 1.0, 0.0], Some(1)),
+        ];
+        bones[1].addition = Some(Addition {
+            rotation: true,
+            translation: true,
+            local: false,
+            bone: Some(0),
+            ratio: 0.5,
+        });
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(3, 0);
+        pose.bones[0].rotation = rotation_z(std::f32::consts::FRAC_PI_2);
+        pose.bones[0].translation = [0.0, 0.0, 2.0];
+        evaluator.evaluate(&pose);
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(close(evaluator.world_position(1), [1.0, 0.0, 1.0]));
+        assert!(close(
+            evaluator.world_position(2),
+            [1.0 - 2.0 * s, 0.0, 1.0]
+        ));
+    }
+
+    #[test]
+    fn local_addition() {
+        let mut bones = vec![
+            bone("a", [0.0, 0.0, 0.0], None),
+            bone("b", [1.0, 0.0, 0.0], None),
+            bone("c", [2.0, 0.0, 0.0], None),
+            bone("d", [2.0, 1.0, 0.0], Some(2)),
+        ];
+        for (i, local) in [(1, false), (2, true)] {
+            bones[i].addition = Some(Addition {
+                rotation: true,
+                translation: true,
+                local,
+                bone: Some(i - 1),
+                ratio: 1.0,
+            });
+        }
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(4, 0);
+        pose.bones[0].rotation = rotation_z(std::f32::consts::FRAC_PI_2);
+        pose.bones[0].translation = [0.0, 0.0, 2.0];
+        pose.bones[1].rotation = rotation_z(std::f32::consts::FRAC_PI_2);
+        pose.bones[1].translation = [0.0, 0.0, 1.0];
+        evaluator.evaluate(&pose);
+        // c only follows the animated part of b, not the grant b got from a.
+        assert!(close(evaluator.world_position(2), [2.0, 0.0, 1.0]));
+        assert!(close(evaluator.world_position(3), [1.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn fixed_pole() {
+        let mut bones = vec![
+            bone("a", [0.0, 0.0, 0.0], None),
+            bone("b", [0.0, 1.0, 0.0], Some(0)),
+        ];
+        bones[0].fixed_pole = Some([0.0, 0.0, 1.0]);
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(2, 0);
+        let x = math::quat_from_axis_angle([1.0, 0.0, 0.0], 0.7);
+        pose.bones[0].rotation = math::quat_mul(x, rotation_z(-std::f32::consts::FRAC_PI_2));
+        evaluator.evaluate(&pose);
+        assert!(close(evaluator.world_position(1), [1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn after_physics() {
+        let mut bones = vec![
+            bone("a", [0.0, 0.0, 0.0], None),
+            bone("b", [0.0, 1.0, 0.0], Some(0)),
+        ];
+        bones[1].after_physics = true;
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(2, 0);
+        pose.bones[1].translation = [1.0, 0.0, 0.0];
+        evaluator.set_pose(&pose);
+        evaluator.update_before_physics();
+        evaluator.set_world_matrix(0, math::translation([0.0, 0.0, 5.0]));
+        evaluator.update_after_physics();
+        assert!(close(evaluator.world_position(1), [1.0, 1.0, 5.0]));
+    }
+
+    #[test]
+    fn local_pole() {
+        let mut bones = vec![bone("a", [0.0, 0.0, 0.0], None)];
+        bones[0].local_pole = Some(LocalPole {
+            x: [0.0, 1.0, 0.0],
+            z: [0.0, 0.0, 1.0],
+        });
+        let skeleton = Skeleton::new(bones).unwrap();
+        let transform = skeleton.local_to_model(
+            0,
+            BoneTransform {
+                translation: [1.0, 0.0, 0.0],
+                rotation: math::QUAT_IDENTITY,
+            },
+        );
+        assert!(close(transform.translation, [0.0, 1.0, 0.0]));
+    }
+
+    fn ik_bone(name: &str, position: Vec3, target: usize, links: Vec<IkLink>) -> Bone {
+        let mut bone = bone(name, position, None);
+        bone.ik = Some(Ik {
+            target_bone: Some(target),
+            loop_count: 40,
+            angle: 1.0,
+            links,
+        });
+        bone
+    }
+
+    fn distance(a: Vec3, b: Vec3) -> f32 {
+        math::length(math::sub(a, b))
+    }
+
+    #[test]
+    fn ik() {
+        let links = vec![
+            IkLink {
+                bone: Some(2),
+                limit: None,
+            },
+            IkLink {
+                bone: Some(1),
+                limit: None,
+            },
+        ];
+        let bones = vec![
+            bone("base", [0.0, 0.0, 0.0], None),
+            bone("upper", [0.0, 0.0, 0.0], Some(0)),
+            bone("lower", [0.0, 1.0, 0.0], Some(1)),
+            bone("tip", [0.0, 2.0, 0.0], Some(2)),
+            ik_bone("ik", [0.0, 2.0, 0.0], 3, links),
+        ];
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(5, 0);
+        pose.bones[4].translation = [1.0, -1.0, 0.5];
+        evaluator.evaluate(&pose);
+        assert!(distance(evaluator.world_position(3), [1.0, 1.0, 0.5]) < 5.0e-3);
+        assert!(close(evaluator.world_position(1), [0.0; 3]));
+        evaluator.set_ik_enabled(4, false);
+        evaluator.evaluate(&pose);
+        assert!(close(evaluator.world_position(3), [0.0, 2.0, 0.0]));
+    }
+
+    #[test]
+    fn knee() {
+        let links = vec![
+            IkLink {
+                bone: Some(1),
+                limit: Some(AngleLimit {
+                    lower: [-PI, 0.0, 0.0],
+                    upper: [-0.5f32.to_radians(), 0.0, 0.0],
+                }),
+            },
+            IkLink {
+                bone: Some(0),
+                limit: None,
+            },
+        ];
+        let bones = vec![
+            bone("leg", [0.0, 2.0, 0.0], None),
+            bone("knee", [0.0, 1.0, 0.0], Some(0)),
+            bone("ankle", [0.0, 0.0, 0.0], Some(1)),
+            ik_bone("leg ik", [0.0, 0.0, 0.0], 2, links),
+        ];
+        let mut evaluator = PoseEvaluator::new(Skeleton::new(bones).unwrap());
+        let mut pose = Pose::new(4, 0);
+        pose.bones[3].translation = [0.0, 0.6, 0.0];
+        evaluator.evaluate(&pose);
+        assert!(distance(evaluator.world_position(2), [0.0, 0.6, 0.0]) < 1.0e-3);
+        let [x, y, z, _] = evaluator.states[1].ik_rotation;
+        assert!(x < 0.0 && y.abs() < 1.0e-6 && z.abs() < 1.0e-6);
+        let knee = evaluator.world_position(1);
+        assert!(knee[2] < 0.0);
+    }
+
+    #[test]
+    fn decompose_euler() {
+        let euler = [0.3, -0.4, 1.2];
+        let q = euler_to_quat(euler);
+        assert!(close(decompose(&q, [0.0; 3]), euler));
+    }
+
+    #[test]
+    fn leg_ik() {
+        let reader =
+            Reader::from_bytes(include_bytes!("../assets/Alicia/Alicia_solid.pmx")).unwrap();
+        let skeleton = Skeleton::from_reader(&reader).unwrap();
+        let ik = skeleton.find("左足ＩＫ").unwrap();
+        let target = skeleton.bones()[ik]
+            .ik
+            .as_ref()
+            .unwrap()
+            .target_bone
+            .unwrap();
+        let mut evaluator = PoseEvaluator::new(skeleton.clone());
+        let mut pose = Pose::new(skeleton.len(), 0);
+        pose.bones[ik].translation = [0.0, 2.0, -1.0];
+        evaluator.evaluate(&pose);
+        let goal = evaluator.world_position(ik);
+        assert!(distance(evaluator.world_position(target), goal) < 0.05);
+    }
+}
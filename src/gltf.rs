@@ -0,0 +1,607 @@
+use super::*;
+use crate::math;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const GLB_JSON: u32 = 0x4e4f_534a;
+const GLB_BIN: u32 = 0x004e_4942;
+
+// MMD is left-handed and glTF is right-handed, so Z is mirrored.
+fn flip(v: [f32; 3]) -> [f32; 3] {
+    [v[0], v[1], -v[2]]
+}
+
+#[derive(Default)]
+struct Builder {
+    buffer: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Builder {
+    fn view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        self.buffer.resize(self.buffer.len().next_multiple_of(4), 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.buffer.extend_from_slice(bytes);
+        self.views.push(view);
+        self.views.len() - 1
+    }
+
+    fn accessor(&mut self, accessor: Value) -> usize {
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn floats<const N: usize>(&mut self, values: &[[f32; N]], kind: &str, bounds: bool) -> usize {
+        let bytes = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.view(&bytes, Some(ARRAY_BUFFER));
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": values.len(),
+            "type": kind,
+        });
+        if bounds {
+            let (min, max) = min_max(values);
+            accessor["min"] = json!(min.to_vec());
+            accessor["max"] = json!(max.to_vec());
+        }
+        self.accessor(accessor)
+    }
+
+    // Morph targets only touch a few vertices, so they are stored sparsely.
+    fn sparse<const N: usize>(
+        &mut self,
+        count: usize,
+        deltas: &[(u32, [f32; N])],
+        kind: &str,
+    ) -> usize {
+        let values = deltas.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        let (min, max) = if values.is_empty() {
+            ([0.0; N], [0.0; N])
+        } else {
+            let (min, max) = min_max(&values);
+            (min.map(|v| v.min(0.0)), max.map(|v| v.max(0.0)))
+        };
+        let mut accessor = json!({
+            "componentType": FLOAT,
+            "count": count,
+            "type": kind,
+            "min": min.to_vec(),
+            "max": max.to_vec(),
+        });
+        if !deltas.is_empty() {
+            let indices = deltas
+                .iter()
+                .flat_map(|(i, _)| i.to_le_bytes())
+                .collect::<Vec<_>>();
+            let bytes = values
+                .iter()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<_>>();
+            accessor["sparse"] = json!({
+                "count": deltas.len(),
+                "indices": {"bufferView": self.view(&indices, None), "componentType": UNSIGNED_INT},
+                "values": {"bufferView": self.view(&bytes, None)},
+            });
+        }
+        self.accessor(accessor)
+    }
+}
+
+fn min_max<const N: usize>(values: &[[f32; N]]) -> ([f32; N], [f32; N]) {
+    let mut min = [f32::MAX; N];
+    let mut max = [f32::MIN; N];
+    for v in values {
+        for i in 0..N {
+            min[i] = min[i].min(v[i]);
+            max[i] = max[i].max(v[i]);
+        }
+    }
+    (min, max)
+}
+
+// Offsets for the same vertex add up like in `MorphEvaluator`.
+fn sum_deltas<const N: usize>(
+    deltas: impl Iterator<Item = (u32, [f32; N])>,
+) -> Vec<(u32, [f32; N])> {
+    let mut deltas = deltas.collect::<Vec<_>>();
+    deltas.sort_by_key(|(i, _)| *i);
+    let mut summed: Vec<(u32, [f32; N])> = Vec::with_capacity(deltas.len());
+    for (i, v) in deltas {
+        match summed.last_mut() {
+            Some((last, sum)) if *last == i => *sum = std::array::from_fn(|j| sum[j] + v[j]),
+            _ => summed.push((i, v)),
+        }
+    }
+    summed
+}
+
+fn material(model: &Model, m: &Material) -> Value {
+    // Blinn-Phong exponent to roughness.
+    let roughness = (2.0 / (m.specular_power.max(0.0) + 2.0)).sqrt();
+    let mut pbr = json!({
+        "baseColorFactor": m.diffuse,
+        "metallicFactor": 0.0,
+        "roughnessFactor": roughness,
+    });
+    if let Some(t) = m.texture.filter(|&t| t < model.textures.len()) {
+        pbr["baseColorTexture"] = json!({"index": t});
+    }
+    let toon = match m.toon {
+        Toon::Texture(t) => json!({"texture": t}),
+        Toon::Shared(n) => json!({"shared": n, "name": shared_toon_name(n)}),
+    };
+    let sphere_mode = match m.sphere_mode {
+        SphereMode::None => "none",
+        SphereMode::Mul => "mul",
+        SphereMode::Add => "add",
+        SphereMode::SubTexture => "subTexture",
+    };
+    json!({
+        "name": m.name,
+        "pbrMetallicRoughness": pbr,
+        "alphaMode": if m.diffuse[3] < 1.0 { "BLEND" } else { "OPAQUE" },
+        "doubleSided": m.both,
+        "extras": {
+            "mmd": {
+                "nameEn": m.name_en,
+                "diffuse": m.diffuse,
+                "specular": m.specular,
+                "specularPower": m.specular_power,
+                "ambient": m.ambient,
+                "toon": toon,
+                "sphere": {"texture": m.sphere, "mode": sphere_mode},
+                "edge": {"enabled": m.edge, "color": m.edge_color, "size": m.edge_size},
+                "groundShadow": m.ground_shadow,
+                "selfShadowMap": m.self_shadow_map,
+                "selfShadow": m.self_shadow,
+                "memo": m.memo,
+            }
+        }
+    })
+}
+
+// URIs are percent-encoded per segment so spaces and non-ASCII names stay valid.
+fn texture_uri(path: &Path) -> String {
+    path.to_string_lossy()
+        .split(['\\', '/'])
+        .map(|segment| {
+            segment
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        (b as char).to_string()
+                    }
+                    _ => format!("%{b:02X}"),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[derive(Clone, Debug)]
+pub struct Gltf {
+    pub json: Value,
+    pub buffer: Vec<u8>,
+}
+
+impl Gltf {
+    pub fn from_model(model: &Model) -> Result<Self, Error> {
+        let skeleton = Skeleton::from_model(model)?;
+        let ranges = model.material_ranges()?;
+        let faces = model.index_buffer()?;
+        let mut builder = Builder::default();
+
+        let positions = model
+            .vertices
+            .iter()
+            .map(|v| flip(v.position))
+            .collect::<Vec<_>>();
+        let normals = model
+            .vertices
+            .iter()
+            .map(|v| flip(math::normalize(v.normal)))
+            .collect::<Vec<_>>();
+        let uvs = model.vertices.iter().map(|v| v.uv).collect::<Vec<_>>();
+        let mut attributes = json!({
+            "POSITION": builder.floats(&positions, "VEC3", true),
+            "NORMAL": builder.floats(&normals, "VEC3", false),
+            "TEXCOORD_0": builder.floats(&uvs, "VEC2", false),
+        });
+        if !skeleton.is_empty() {
+            // JOINTS_0 may only use unsigned bytes or shorts.
+            if skeleton.len() > u16::MAX as usize + 1 {
+                return Err(Error::invalid_data(format!(
+                    "{} bones do not fit in glTF joint indices",
+                    skeleton.len()
+                ))
+                .in_element(Section::Bones, None));
+            }
+            let mut joints = vec![];
+            let mut weights = vec![];
+            for v in &model.vertices {
+                let (mut indices, mut values) = v.weight.influences();
+                // Bones outside the skin get no weight, so the rest is renormalized.
+                for (i, w) in indices.iter_mut().zip(&mut values) {
+                    if *i as usize >= skeleton.len() {
+                        (*i, *w) = (0, 0.0);
+                    }
+                }
+                let sum = values.iter().sum::<f32>();
+                if sum > 0.0 {
+                    values = values.map(|w| w / sum);
+                } else {
+                    (indices, values) = ([0; 4], [1.0, 0.0, 0.0, 0.0]);
+                }
+                joints.extend(indices.iter().flat_map(|&i| (i as u16).to_le_bytes()));
+                weights.push(values);
+            }
+            let view = builder.view(&joints, Some(ARRAY_BUFFER));
+            attributes["JOINTS_0"] = json!(builder.accessor(json!({
+                "bufferView": view,
+                "componentType": UNSIGNED_SHORT,
+                "count": model.vertices.len(),
+                "type": "VEC4",
+            })));
+            attributes["WEIGHTS_0"] = json!(builder.floats(&weights, "VEC4", false));
+        }
+
+        let mut targets = vec![];
+        let mut target_names = vec![];
+        for morph in &model.morphs {
+            let target = match &morph.kind {
+                morph::Kind::Vertex(offsets) => {
+                    let deltas = sum_deltas(
+                        offsets
+                            .iter()
+                            .filter(|o| o.vertex < model.vertices.len())
+                            .map(|o| (o.vertex as u32, flip(o.offset))),
+                    );
+                    json!({"POSITION": builder.sparse(model.vertices.len(), &deltas, "VEC3")})
+                }
+                morph::Kind::Uv(offsets) => {
+                    let deltas = sum_deltas(
+                        offsets
+                            .iter()
+                            .filter(|o| o.vertex < model.vertices.len())
+                            .map(|o| (o.vertex as u32, [o.offset[0], o.offset[1]])),
+                    );
+                    json!({"TEXCOORD_0": builder.sparse(model.vertices.len(), &deltas, "VEC2")})
+                }
+                _ => continue,
+            };
+            targets.push(target);
+            target_names.push(morph.name.clone());
+        }
+
+        // Winding is reversed along with the mirrored Z axis.
+        let indices = match &faces {
+            IndexBuffer::U16(v) => v.iter().map(|&i| i as u32).collect::<Vec<_>>(),
+            IndexBuffer::U32(v) => v.clone(),
+        };
+        let mut primitives = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            if range.is_empty() {
+                continue;
+            }
+            let bytes = indices[range.clone()]
+                .chunks(3)
+                .flat_map(|f| [f[0], f[2], f[1]])
+                .flat_map(|i| i.to_le_bytes())
+                .collect::<Vec<_>>();
+            let view = builder.view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+            let accessor = builder.accessor(json!({
+                "bufferView": view,
+                "componentType": UNSIGNED_INT,
+                "count": range.len(),
+                "type": "SCALAR",
+            }));
+            let mut primitive = json!({
+                "attributes": attributes,
+                "indices": accessor,
+                "material": i,
+            });
+            if !targets.is_empty() {
+                primitive["targets"] = json!(targets);
+            }
+            primitives.push(primitive);
+        }
+
+        let mut nodes = vec![];
+        for (i, bone) in model.bones.iter().enumerate() {
+            let mut node = json!({
+                "name": bone.name,
+                "translation": flip(skeleton.local_offset(i)),
+            });
+            if !skeleton.children(i).is_empty() {
+                node["children"] = json!(skeleton.children(i));
+            }
+            nodes.push(node);
+        }
+        let mesh_node = nodes.len();
+        let mut mesh = json!({
+            "name": model.name,
+            "primitives": primitives,
+        });
+        if !target_names.is_empty() {
+            mesh["weights"] = json!(vec![0.0; target_names.len()]);
+            mesh["extras"] = json!({"targetNames": target_names});
+        }
+        let mut node = json!({"name": model.name, "mesh": 0});
+        let mut skins = vec![];
+        if !skeleton.is_empty() {
+            let inverse_binds = (0..skeleton.len())
+                .map(|i| {
+                    let m = math::translation(math::scale(flip(model.bones[i].position), -1.0));
+                    std::array::from_fn::<f32, 16, _>(|j| m[j / 4][j % 4])
+                })
+                .collect::<Vec<_>>();
+            let bytes = inverse_binds
+                .iter()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<_>>();
+            let view = builder.view(&bytes, None);
+            let accessor = builder.accessor(json!({
+                "bufferView": view,
+                "componentType": FLOAT,
+                "count": skeleton.len(),
+                "type": "MAT4",
+            }));
+            let mut skin = json!({
+                "inverseBindMatrices": accessor,
+                "joints": (0..skeleton.len()).collect::<Vec<_>>(),
+            });
+            if let [root] = skeleton.roots() {
+                skin["skeleton"] = json!(root);
+            }
+            skins.push(skin);
+            node["skin"] = json!(0);
+        }
+        nodes.push(node);
+        let mut scene_nodes = skeleton.roots().to_vec();
+        scene_nodes.push(mesh_node);
+
+        let images = model
+            .textures
+            .iter()
+            .map(|t| json!({"uri": texture_uri(t)}))
+            .collect::<Vec<_>>();
+        let textures = (0..model.textures.len())
+            .map(|i| json!({"source": i, "sampler": 0}))
+            .collect::<Vec<_>>();
+        let materials = model
+            .materials
+            .iter()
+            .map(|m| material(model, m))
+            .collect::<Vec<_>>();
+
+        let mut json = json!({
+            "asset": {"version": "2.0", "generator": "pmx"},
+            "scene": 0,
+            "scenes": [{"name": model.name, "nodes": scene_nodes}],
+            "nodes": nodes,
+            "meshes": [mesh],
+            "materials": materials,
+            "accessors": builder.accessors,
+            "bufferViews": builder.views,
+            "buffers": [{"byteLength": builder.buffer.len()}],
+            "extras": {
+                "mmd": {
+                    "name": model.name,
+                    "nameEn": model.name_en,
+                    "comment": model.comment,
+                    "commentEn": model.comment_en,
+                }
+            },
+        });
+        if !skins.is_empty() {
+            json["skins"] = json!(skins);
+        }
+        if !images.is_empty() {
+            json["images"] = json!(images);
+            json["textures"] = json!(textures);
+            json["samplers"] = json!([{}]);
+        }
+        Ok(Self {
+            json,
+            buffer: builder.buffer,
+        })
+    }
+
+    pub fn write_glb<W: Write>(&self, mut writer: W) -> Result<W, Error> {
+        let mut json = serde_json::to_vec(&self.json).map_err(std::io::Error::other)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = self.buffer.clone();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        for v in [GLB_MAGIC, 2, length as u32, json.len() as u32, GLB_JSON] {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.write_all(&json)?;
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(&GLB_BIN.to_le_bytes())?;
+        writer.write_all(&bin)?;
+        Ok(writer)
+    }
+
+    pub fn save_glb(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.write_glb(BufWriter::new(file))?.flush()?;
+        Ok(())
+    }
+
+    // Writes the JSON to `path` and the buffer next to it with a `.bin` extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let bin = path.with_extension("bin");
+        let mut json = self.json.clone();
+        json["buffers"][0]["uri"] = json!(bin.file_name().map(|n| n.to_string_lossy()));
+        std::fs::write(&bin, &self.buffer)?;
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &json).map_err(std::io::Error::other)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accessor_bytes<'a>(gltf: &'a Gltf, accessor: &Value) -> &'a [u8] {
+        let view = &gltf.json["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        let len = view["byteLength"].as_u64().unwrap() as usize;
+        &gltf.buffer[offset..offset + len]
+    }
+
+    #[test]
+    fn export() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let gltf = Gltf::from_model(&model).unwrap();
+        let json = &gltf.json;
+        let primitives = json["meshes"][0]["primitives"].as_array().unwrap();
+        let non_empty = model.materials.iter().filter(|m| m.index_count > 0).count();
+        assert!(primitives.len() == non_empty);
+        let indices: u64 = primitives
+            .iter()
+            .map(|p| {
+                json["accessors"][p["indices"].as_u64().unwrap() as usize]["count"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .sum();
+        assert!(indices as usize == model.faces.len());
+        assert!(json["skins"][0]["joints"].as_array().unwrap().len() == model.bones.len());
+        assert!(json["nodes"].as_array().unwrap().len() == model.bones.len() + 1);
+        let targets = model
+            .morphs
+            .iter()
+            .filter(|m| matches!(m.kind, morph::Kind::Vertex(_) | morph::Kind::Uv(_)))
+            .count();
+        assert!(
+            json["meshes"][0]["extras"]["targetNames"]
+                .as_array()
+                .unwrap()
+                .len()
+                == targets
+        );
+        assert!(json["images"].as_array().unwrap().len() == model.textures.len());
+        let material = &json["materials"][0];
+        assert!(
+            material["extras"]["mmd"]["edge"]["size"].as_f64().unwrap() as f32
+                == model.materials[0].edge_size
+        );
+
+        let position =
+            &json["accessors"][primitives[0]["attributes"]["POSITION"].as_u64().unwrap() as usize];
+        let bytes = accessor_bytes(&gltf, position);
+        let z = f32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        assert!(z == -model.vertices[0].position[2]);
+        let first = &json["accessors"][primitives[0]["indices"].as_u64().unwrap() as usize];
+        let bytes = accessor_bytes(&gltf, first);
+        let face = [0, 1, 2]
+            .map(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()) as usize);
+        assert!(face == [model.faces[0], model.faces[2], model.faces[1]]);
+    }
+
+    #[test]
+    fn weights() {
+        let mut model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        model.vertices[0].weight = Weight::Bdef4(Bdef4 {
+            bones: [Some(0), Some(1), Some(2), None],
+            weights: [0.5, 0.5, 0.5, 0.0],
+        });
+        model.vertices[1].weight = Weight::Bdef2(Bdef2 {
+            bones: [Some(1), Some(100_000)],
+            weight: 0.25,
+        });
+        let gltf = Gltf::from_model(&model).unwrap();
+        let primitive = &gltf.json["meshes"][0]["primitives"][0];
+        let joints =
+            &gltf.json["accessors"][primitive["attributes"]["JOINTS_0"].as_u64().unwrap() as usize];
+        let joints = accessor_bytes(&gltf, joints)
+            .chunks(2)
+            .map(|b| u16::from_le_bytes(b.try_into().unwrap()) as usize)
+            .collect::<Vec<_>>();
+        assert!(joints.iter().all(|&j| j < model.bones.len()));
+        assert!(joints[4..8] == [1, 0, 0, 0]);
+        let weights = &gltf.json["accessors"]
+            [primitive["attributes"]["WEIGHTS_0"].as_u64().unwrap() as usize];
+        let bytes = accessor_bytes(&gltf, weights);
+        for vertex in bytes.chunks(16) {
+            let sum = vertex
+                .chunks(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .sum::<f32>();
+            assert!((sum - 1.0).abs() < 1e-5);
+        }
+        let first = f32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        assert!((first - 1.0 / 3.0).abs() < 1e-6);
+        let second = f32::from_le_bytes(bytes[16..20].try_into().unwrap());
+        assert!(second == 1.0);
+    }
+
+    #[test]
+    fn summed_deltas() {
+        let deltas = sum_deltas([(3, [1.0, 2.0]), (1, [1.0, 0.0]), (3, [0.5, 0.5])].into_iter());
+        assert!(deltas == [(1, [1.0, 0.0]), (3, [1.5, 2.5])]);
+    }
+
+    #[test]
+    fn uri() {
+        assert!(texture_uri(Path::new("tex\\a b.png")) == "tex/a%20b.png");
+        assert!(texture_uri(Path::new("../顔.png")) == "../%E9%A1%94.png");
+    }
+
+    #[test]
+    fn glb() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let gltf = Gltf::from_model(&model).unwrap();
+        let data = gltf.write_glb(vec![]).unwrap();
+        assert!(data.len().is_multiple_of(4));
+        let word = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        assert!(word(0) == GLB_MAGIC);
+        assert!(word(2) as usize == data.len());
+        let json_len = word(3) as usize;
+        let json: Value = serde_json::from_slice(&data[20..20 + json_len]).unwrap();
+        assert!(json["asset"]["version"] == "2.0");
+        let bin_len = u32::from_le_bytes(data[20 + json_len..24 + json_len].try_into().unwrap());
+        assert!(bin_len as usize >= gltf.buffer.len());
+
+        let dir = tempfile::tempdir().unwrap();
+        gltf.save(dir.path().join("model.gltf")).unwrap();
+        let json: Value =
+            serde_json::from_reader(File::open(dir.path().join("model.gltf")).unwrap()).unwrap();
+        assert!(json["buffers"][0]["uri"] == "model.bin");
+        assert!(
+            std::fs::metadata(dir.path().join("model.bin"))
+                .unwrap()
+                .len() as usize
+                == gltf.buffer.len()
+        );
+    }
+}
@@ -5,8 +5,9 @@ pub enum Encoding {
     Utf8 = 1,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Header {
+    pub version: f32,
     pub encoding: Encoding,
     pub extended_uv: u8,
     pub vertex_index_size: u64,
@@ -16,3 +17,9 @@ pub struct Header {
     pub morph_index_size: u64,
     pub rigid_index_size: u64,
 }
+
+impl Header {
+    pub(crate) fn is_v2_1(&self) -> bool {
+        self.version >= 2.1
+    }
+}
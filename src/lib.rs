@@ -1,8 +1,49 @@
+mod animation;
+mod binary;
+mod buffer;
+mod error;
+mod evaluator;
+#[cfg(feature = "gltf")]
+mod gltf;
 mod header;
+mod math;
+mod model;
+mod morphing;
+mod obj;
+mod package;
+#[cfg(feature = "physics")]
+mod physics;
+mod pmd;
+mod pose;
 mod reader;
-
+mod skeleton;
+mod skinning;
+mod texture;
+mod vmd;
+mod vpd;
+mod writer;
+
+pub use animation::*;
+pub use buffer::*;
+pub use error::*;
+pub use evaluator::*;
+#[cfg(feature = "gltf")]
+pub use gltf::*;
 pub use header::*;
+pub use model::*;
+pub use morphing::*;
+pub use obj::*;
+pub use package::*;
+#[cfg(feature = "physics")]
+pub use physics::*;
+pub use pose::*;
 pub use reader::*;
+pub use skeleton::*;
+pub use skinning::*;
+pub use texture::*;
+pub use vmd::*;
+pub use vpd::*;
+pub use writer::*;
 
 #[derive(Clone, Debug)]
 pub struct Bdef1 {
@@ -30,12 +71,19 @@ pub struct Sdef {
     pub r1: [f32; 3],
 }
 
+#[derive(Clone, Debug)]
+pub struct Qdef {
+    pub bones: [Option<usize>; 4],
+    pub weights: [f32; 4],
+}
+
 #[derive(Clone, Debug)]
 pub enum Weight {
     Bdef1(Bdef1),
     Bdef2(Bdef2),
     Bdef4(Bdef4),
     Sdef(Sdef),
+    Qdef(Qdef),
 }
 
 #[derive(Clone, Debug)]
@@ -202,6 +250,14 @@ pub mod morph {
         pub ratio: f32,
     }
 
+    #[derive(Clone, Debug)]
+    pub struct Impulse {
+        pub rigid: Option<usize>,
+        pub local: bool,
+        pub velocity: [f32; 3],
+        pub torque: [f32; 3],
+    }
+
     #[derive(Clone, Debug)]
     pub enum Kind {
         Vertex(Vec<Vertex>),
@@ -210,6 +266,8 @@ pub mod morph {
         Material(Vec<Material>),
         Group(Vec<Group>),
         ExtendedUv(usize, Vec<Uv>),
+        Flip(Vec<Group>),
+        Impulse(Vec<Impulse>),
     }
 }
 
@@ -270,10 +328,23 @@ pub struct Rigid {
     pub method: rigid::Method,
 }
 
+pub mod joint {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Kind {
+        Spring6Dof,
+        SixDof,
+        P2P,
+        ConeTwist,
+        Slider,
+        Hinge,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Joint {
     pub name: String,
     pub name_en: String,
+    pub kind: joint::Kind,
     pub rigids: [Option<usize>; 2],
     pub position: [f32; 3],
     pub rotation: [f32; 3],
@@ -282,3 +353,92 @@ pub struct Joint {
     pub spring_translation: [f32; 3],
     pub spring_rotation: [f32; 3],
 }
+
+pub mod soft_body {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Shape {
+        TriMesh,
+        Rope,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum AeroModel {
+        VPoint,
+        VTwoSided,
+        VOneSided,
+        FTwoSided,
+        FOneSided,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Config {
+        pub vcf: f32,
+        pub dp: f32,
+        pub dg: f32,
+        pub lf: f32,
+        pub pr: f32,
+        pub vc: f32,
+        pub df: f32,
+        pub mt: f32,
+        pub chr: f32,
+        pub khr: f32,
+        pub shr: f32,
+        pub ahr: f32,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Cluster {
+        pub srhr_cl: f32,
+        pub skhr_cl: f32,
+        pub sshr_cl: f32,
+        pub sr_splt_cl: f32,
+        pub sk_splt_cl: f32,
+        pub ss_splt_cl: f32,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Iteration {
+        pub v_it: i32,
+        pub p_it: i32,
+        pub d_it: i32,
+        pub c_it: i32,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Material {
+        pub lst: f32,
+        pub ast: f32,
+        pub vst: f32,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Anchor {
+        pub rigid: Option<usize>,
+        pub vertex: usize,
+        pub near_mode: bool,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SoftBody {
+    pub name: String,
+    pub name_en: String,
+    pub shape: soft_body::Shape,
+    pub material: Option<usize>,
+    pub group: u8,
+    pub non_collision_groups: u16,
+    pub b_link: bool,
+    pub generate_clusters: bool,
+    pub link_crossing: bool,
+    pub b_link_distance: i32,
+    pub cluster_count: i32,
+    pub total_mass: f32,
+    pub margin: f32,
+    pub aero_model: soft_body::AeroModel,
+    pub config: soft_body::Config,
+    pub cluster: soft_body::Cluster,
+    pub iteration: soft_body::Iteration,
+    pub material_params: soft_body::Material,
+    pub anchors: Vec<soft_body::Anchor>,
+    pub pins: Vec<usize>,
+}
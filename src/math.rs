@@ -0,0 +1,207 @@
+// Matrices are column-major (`m[column][row]`) and quaternions are `[x, y, z, w]`.
+
+pub(crate) type Vec3 = [f32; 3];
+pub(crate) type Quat = [f32; 4];
+pub(crate) type Mat4 = [[f32; 4]; 4];
+
+pub(crate) const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub(crate) const QUAT_IDENTITY: Quat = [0.0, 0.0, 0.0, 1.0];
+
+pub(crate) fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scale(a: Vec3, s: f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub(crate) fn translation(t: Vec3) -> Mat4 {
+    let mut m = IDENTITY;
+    m[3] = [t[0], t[1], t[2], 1.0];
+    m
+}
+
+pub(crate) fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn length(a: Vec3) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn normalize(a: Vec3) -> Vec3 {
+    let len = length(a);
+    if len > 0.0 {
+        scale(a, 1.0 / len)
+    } else {
+        a
+    }
+}
+
+pub(crate) fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for c in 0..4 {
+        for r in 0..4 {
+            m[c][r] = (0..4).map(|k| a[k][r] * b[c][k]).sum();
+        }
+    }
+    m
+}
+
+pub(crate) fn transform_point(m: &Mat4, p: Vec3) -> Vec3 {
+    [0, 1, 2].map(|r| m[0][r] * p[0] + m[1][r] * p[1] + m[2][r] * p[2] + m[3][r])
+}
+
+pub(crate) fn transform_vector(m: &Mat4, v: Vec3) -> Vec3 {
+    [0, 1, 2].map(|r| m[0][r] * v[0] + m[1][r] * v[1] + m[2][r] * v[2])
+}
+
+pub(crate) fn quat_mul(a: Quat, b: Quat) -> Quat {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+pub(crate) fn quat_conjugate(q: Quat) -> Quat {
+    [-q[0], -q[1], -q[2], q[3]]
+}
+
+pub(crate) fn quat_normalize(q: Quat) -> Quat {
+    let len = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    if len > 0.0 {
+        q.map(|v| v / len)
+    } else {
+        QUAT_IDENTITY
+    }
+}
+
+pub(crate) fn quat_from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+    let (s, c) = (angle * 0.5).sin_cos();
+    let axis = normalize(axis);
+    [axis[0] * s, axis[1] * s, axis[2] * s, c]
+}
+
+pub(crate) fn quat_rotate(q: Quat, v: Vec3) -> Vec3 {
+    let u = [q[0], q[1], q[2]];
+    let t = scale(cross(u, v), 2.0);
+    add(add(v, scale(t, q[3])), cross(u, t))
+}
+
+pub(crate) fn slerp(a: Quat, b: Quat, t: f32) -> Quat {
+    let mut d = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    let b = if d < 0.0 {
+        d = -d;
+        b.map(|v| -v)
+    } else {
+        b
+    };
+    if d > 0.9995 {
+        return quat_normalize([0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t));
+    }
+    let theta = d.acos();
+    let s = theta.sin();
+    let wa = ((1.0 - t) * theta).sin() / s;
+    let wb = (t * theta).sin() / s;
+    [0, 1, 2, 3].map(|i| a[i] * wa + b[i] * wb)
+}
+
+pub(crate) fn quat_from_basis(x: Vec3, y: Vec3, z: Vec3) -> Quat {
+    let trace = x[0] + y[1] + z[2];
+    let q = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [
+            (y[2] - z[1]) / s,
+            (z[0] - x[2]) / s,
+            (x[1] - y[0]) / s,
+            0.25 * s,
+        ]
+    } else if x[0] > y[1] && x[0] > z[2] {
+        let s = (1.0 + x[0] - y[1] - z[2]).sqrt() * 2.0;
+        [
+            0.25 * s,
+            (y[0] + x[1]) / s,
+            (z[0] + x[2]) / s,
+            (y[2] - z[1]) / s,
+        ]
+    } else if y[1] > z[2] {
+        let s = (1.0 + y[1] - x[0] - z[2]).sqrt() * 2.0;
+        [
+            (y[0] + x[1]) / s,
+            0.25 * s,
+            (z[1] + y[2]) / s,
+            (z[0] - x[2]) / s,
+        ]
+    } else {
+        let s = (1.0 + z[2] - x[0] - y[1]).sqrt() * 2.0;
+        [
+            (z[0] + x[2]) / s,
+            (z[1] + y[2]) / s,
+            0.25 * s,
+            (x[1] - y[0]) / s,
+        ]
+    };
+    quat_normalize(q)
+}
+
+pub(crate) fn from_rotation_translation(q: Quat, t: Vec3) -> Mat4 {
+    let [x, y, z, w] = q;
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + z * w),
+            2.0 * (x * z - y * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * y - z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + x * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * z + y * w),
+            2.0 * (y * z - x * w),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ],
+        [t[0], t[1], t[2], 1.0],
+    ]
+}
+
+pub(crate) fn inverse_transform_point(m: &Mat4, p: Vec3) -> Vec3 {
+    let d = sub(p, [m[3][0], m[3][1], m[3][2]]);
+    [0, 1, 2].map(|c| m[c][0] * d[0] + m[c][1] * d[1] + m[c][2] * d[2])
+}
+
+pub(crate) fn inverse_rigid(m: &Mat4) -> Mat4 {
+    let t = inverse_transform_point(m, [0.0; 3]);
+    let mut inverse = IDENTITY;
+    for c in 0..3 {
+        for r in 0..3 {
+            inverse[c][r] = m[r][c];
+        }
+    }
+    inverse[3] = [t[0], t[1], t[2], 1.0];
+    inverse
+}
@@ -0,0 +1,160 @@
+use super::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+fn signed_index_size(len: usize) -> u64 {
+    if len <= i8::MAX as usize {
+        1
+    } else if len <= i16::MAX as usize {
+        2
+    } else {
+        4
+    }
+}
+
+fn vertex_index_size(len: usize) -> u64 {
+    if len <= u8::MAX as usize {
+        1
+    } else if len <= u16::MAX as usize {
+        2
+    } else {
+        4
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Model {
+    pub header: Header,
+    pub name: String,
+    pub name_en: String,
+    pub comment: String,
+    pub comment_en: String,
+    pub vertices: Vec<Vertex>,
+    pub faces: Vec<usize>,
+    pub textures: Vec<PathBuf>,
+    pub materials: Vec<Material>,
+    pub bones: Vec<Bone>,
+    pub morphs: Vec<Morph>,
+    pub display_groups: Vec<DisplayGroup>,
+    pub rigids: Vec<Rigid>,
+    pub joints: Vec<Joint>,
+    pub soft_bodies: Vec<SoftBody>,
+}
+
+impl Model {
+    pub fn from_reader(reader: &Reader) -> Result<Self, Error> {
+        Ok(Self {
+            header: reader.header().clone(),
+            name: reader.name()?,
+            name_en: reader.name_en()?,
+            comment: reader.comment()?,
+            comment_en: reader.comment_en()?,
+            vertices: reader.vertices().collect::<Result<_, _>>()?,
+            faces: reader.faces().collect::<Result<_, _>>()?,
+            textures: reader.textures().collect::<Result<_, _>>()?,
+            materials: reader.materials().collect::<Result<_, _>>()?,
+            bones: reader.bones().collect::<Result<_, _>>()?,
+            morphs: reader.morphs().collect::<Result<_, _>>()?,
+            display_groups: reader.display_groups().collect::<Result<_, _>>()?,
+            rigids: reader.rigids().collect::<Result<_, _>>()?,
+            joints: reader.joints().collect::<Result<_, _>>()?,
+            soft_bodies: reader.soft_bodies().collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let reader = Reader::new(BufReader::new(file))?;
+        Self::from_reader(&reader)
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<W, Error> {
+        let mut writer = Writer::new(writer, self.header.clone())?;
+        writer.write_info(&self.name, &self.name_en, &self.comment, &self.comment_en)?;
+        writer.write_vertices(&self.vertices)?;
+        writer.write_faces(&self.faces)?;
+        writer.write_textures(&self.textures)?;
+        writer.write_materials(&self.materials)?;
+        writer.write_bones(&self.bones)?;
+        writer.write_morphs(&self.morphs)?;
+        writer.write_display_groups(&self.display_groups)?;
+        writer.write_rigids(&self.rigids)?;
+        writer.write_joints(&self.joints)?;
+        if self.header.is_v2_1() || !self.soft_bodies.is_empty() {
+            writer.write_soft_bodies(&self.soft_bodies)?;
+        }
+        writer.finish()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))?;
+        Ok(())
+    }
+
+    pub fn update_index_sizes(&mut self) {
+        self.header.vertex_index_size = vertex_index_size(self.vertices.len());
+        self.header.texture_index_size = signed_index_size(self.textures.len());
+        self.header.material_index_size = signed_index_size(self.materials.len());
+        self.header.bone_index_size = signed_index_size(self.bones.len());
+        self.header.morph_index_size = signed_index_size(self.morphs.len());
+        self.header.rigid_index_size = signed_index_size(self.rigids.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const DATA: &[u8] = include_bytes!("../assets/Alicia/Alicia_solid.pmx");
+
+    fn new_model() -> Model {
+        Model::from_reader(&Reader::new(Cursor::new(DATA)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn load() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        assert!(model.name == "アリシア・ソリッド");
+        assert!(model.vertices.len() == 22311);
+        assert!(model.faces.len() == 95598);
+        assert!(model.joints.len() == 53);
+    }
+
+    #[test]
+    fn write() {
+        let model = new_model();
+        let data = model.write(vec![]).unwrap();
+        assert!(data == DATA);
+    }
+
+    #[test]
+    fn edit() {
+        let mut model = new_model();
+        model.bones[0].name = "root".to_string();
+        model.materials.pop();
+        let data = model.write(vec![]).unwrap();
+        let reader = Reader::new(Cursor::new(data)).unwrap();
+        assert!(reader.bones().next().unwrap().unwrap().name == "root");
+        assert!(reader.materials().len() == 21);
+    }
+
+    #[test]
+    fn update_index_sizes() {
+        let mut model = new_model();
+        let bone = model.bones[0].clone();
+        model.bones.resize(200, bone);
+        assert!(model.write(vec![]).is_ok());
+        model.bones.resize(40000, model.bones[0].clone());
+        model.vertices[0].weight = Weight::Bdef1(Bdef1 { bone: Some(39999) });
+        assert!(model.write(vec![]).is_err());
+        model.update_index_sizes();
+        assert!(model.header.bone_index_size == 4);
+        assert!(model.header.vertex_index_size == 2);
+        let data = model.write(vec![]).unwrap();
+        let reader = Reader::new(Cursor::new(data)).unwrap();
+        assert!(reader.bones().len() == 40000);
+    }
+}
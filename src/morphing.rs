@@ -0,0 +1,559 @@
+use super::*;
+use crate::math;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MaterialParams {
+    pub diffuse: [f32; 4],
+    pub specular: [f32; 3],
+    pub specular_power: f32,
+    pub ambient: [f32; 3],
+    pub edge_color: [f32; 4],
+    pub edge_size: f32,
+    pub texture: [f32; 4],
+    pub sphere: [f32; 4],
+    pub toon: [f32; 4],
+}
+
+impl MaterialParams {
+    pub const ZERO: Self = Self::splat(0.0);
+    pub const ONE: Self = Self::splat(1.0);
+
+    const fn splat(v: f32) -> Self {
+        Self {
+            diffuse: [v; 4],
+            specular: [v; 3],
+            specular_power: v,
+            ambient: [v; 3],
+            edge_color: [v; 4],
+            edge_size: v,
+            texture: [v; 4],
+            sphere: [v; 4],
+            toon: [v; 4],
+        }
+    }
+
+    fn from_morph(m: &morph::Material) -> Self {
+        Self {
+            diffuse: m.diffuse,
+            specular: m.specular,
+            specular_power: m.specular_power,
+            ambient: m.ambient,
+            edge_color: m.edge_color,
+            edge_size: m.edge_size,
+            texture: m.texture,
+            sphere: m.sphere,
+            toon: m.toon,
+        }
+    }
+
+    fn zip(&self, other: &Self, f: impl Fn(f32, f32) -> f32) -> Self {
+        fn each<const N: usize>(
+            a: [f32; N],
+            b: [f32; N],
+            f: &impl Fn(f32, f32) -> f32,
+        ) -> [f32; N] {
+            std::array::from_fn(|i| f(a[i], b[i]))
+        }
+        Self {
+            diffuse: each(self.diffuse, other.diffuse, &f),
+            specular: each(self.specular, other.specular, &f),
+            specular_power: f(self.specular_power, other.specular_power),
+            ambient: each(self.ambient, other.ambient, &f),
+            edge_color: each(self.edge_color, other.edge_color, &f),
+            edge_size: f(self.edge_size, other.edge_size),
+            texture: each(self.texture, other.texture, &f),
+            sphere: each(self.sphere, other.sphere, &f),
+            toon: each(self.toon, other.toon, &f),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MaterialDelta {
+    pub mul: MaterialParams,
+    pub add: MaterialParams,
+}
+
+impl Default for MaterialDelta {
+    fn default() -> Self {
+        Self {
+            mul: MaterialParams::ONE,
+            add: MaterialParams::ZERO,
+        }
+    }
+}
+
+impl MaterialDelta {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Texture, sphere and toon factors have no counterpart in `Material` and
+    // are left to the renderer as `mul * color + add`.
+    pub fn apply(&self, material: &Material) -> Material {
+        let mut base = MaterialParams::ONE;
+        base.diffuse = material.diffuse;
+        base.specular = material.specular;
+        base.specular_power = material.specular_power;
+        base.ambient = material.ambient;
+        base.edge_color = material.edge_color;
+        base.edge_size = material.edge_size;
+        let p = base
+            .zip(&self.mul, |b, m| b * m)
+            .zip(&self.add, |v, a| v + a);
+        Material {
+            diffuse: p.diffuse,
+            specular: p.specular,
+            specular_power: p.specular_power,
+            ambient: p.ambient,
+            edge_color: p.edge_color,
+            edge_size: p.edge_size,
+            ..material.clone()
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct MorphDeltas {
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 4]>,
+    pub extended_uvs: Vec<Vec<[f32; 4]>>,
+    pub bones: Vec<BoneTransform>,
+    pub materials: Vec<MaterialDelta>,
+}
+
+impl MorphDeltas {
+    pub fn apply_to_vertices(&self, vertices: &mut [Vertex]) {
+        for (v, offset) in vertices.iter_mut().zip(&self.positions) {
+            v.position = math::add(v.position, *offset);
+        }
+        for (v, uv) in vertices.iter_mut().zip(&self.uvs) {
+            v.uv = [v.uv[0] + uv[0], v.uv[1] + uv[1]];
+        }
+        for (ch, offsets) in self.extended_uvs.iter().enumerate() {
+            for (v, offset) in vertices.iter_mut().zip(offsets) {
+                if let Some(uv) = v.extended_uv.get_mut(ch) {
+                    *uv = std::array::from_fn(|j| uv[j] + offset[j]);
+                }
+            }
+        }
+    }
+
+    pub fn apply_to_pose(&self, pose: &mut Pose) {
+        if pose.bones.len() < self.bones.len() {
+            pose.bones.resize(self.bones.len(), BoneTransform::IDENTITY);
+        }
+        for (transform, delta) in pose.bones.iter_mut().zip(&self.bones) {
+            transform.translation = math::add(transform.translation, delta.translation);
+            transform.rotation = math::quat_mul(transform.rotation, delta.rotation);
+        }
+    }
+
+    pub fn apply_to_materials(&self, materials: &[Material]) -> Vec<Material> {
+        materials
+            .iter()
+            .zip(&self.materials)
+            .map(|(m, delta)| delta.apply(m))
+            .collect()
+    }
+}
+
+/// Evaluates every morph kind except impulse morphs, which act on rigid bodies
+/// and need a physics simulation.
+#[derive(Clone, Debug)]
+pub struct MorphEvaluator {
+    morphs: Vec<Morph>,
+    vertices_len: usize,
+    extended_uv: usize,
+    bones_len: usize,
+    materials_len: usize,
+}
+
+impl MorphEvaluator {
+    pub fn new(
+        morphs: Vec<Morph>,
+        vertices_len: usize,
+        extended_uv: usize,
+        bones_len: usize,
+        materials_len: usize,
+    ) -> Result<Self, Error> {
+        let groups = |i: usize| match &morphs[i].kind {
+            morph::Kind::Group(groups) | morph::Kind::Flip(groups) => groups.as_slice(),
+            _ => &[],
+        };
+        for (i, m) in morphs.iter().enumerate() {
+            if let Some(g) = groups(i)
+                .iter()
+                .find(|g| g.morph.is_some_and(|m| m >= morphs.len()))
+            {
+                let kind = match m.kind {
+                    morph::Kind::Flip(_) => "flip",
+                    _ => "group",
+                };
+                return Err(Error::invalid_data(format!("{kind} morph {:?}", g.morph))
+                    .in_element(Section::Morphs, Some(i)));
+            }
+        }
+        // 0: unvisited, 1: on the current path, 2: done
+        let mut state = vec![0u8; morphs.len()];
+        for root in 0..morphs.len() {
+            if state[root] != 0 {
+                continue;
+            }
+            state[root] = 1;
+            let mut stack = vec![(root, 0)];
+            while let Some((i, next)) = stack.pop() {
+                let Some(child) = groups(i).get(next) else {
+                    state[i] = 2;
+                    continue;
+                };
+                stack.push((i, next + 1));
+                let Some(child) = child.morph else {
+                    continue;
+                };
+                match state[child] {
+                    0 => {
+                        state[child] = 1;
+                        stack.push((child, 0));
+                    }
+                    1 => {
+                        return Err(Error::invalid_data("cyclic group or flip morph")
+                            .in_element(Section::Morphs, Some(child)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Self {
+            morphs,
+            vertices_len,
+            extended_uv,
+            bones_len,
+            materials_len,
+        })
+    }
+
+    pub fn from_reader(reader: &Reader) -> Result<Self, Error> {
+        Self::new(
+            reader.morphs().collect::<Result<_, _>>()?,
+            reader.vertices().len(),
+            reader.header().extended_uv as usize,
+            reader.bones().len(),
+            reader.materials().len(),
+        )
+    }
+
+    pub fn from_model(model: &Model) -> Result<Self, Error> {
+        Self::new(
+            model.morphs.clone(),
+            model.vertices.len(),
+            model.header.extended_uv as usize,
+            model.bones.len(),
+            model.materials.len(),
+        )
+    }
+
+    pub fn morphs(&self) -> &[Morph] {
+        &self.morphs
+    }
+
+    pub fn evaluate(&self, weights: &[f32]) -> MorphDeltas {
+        let mut deltas = MorphDeltas::default();
+        self.evaluate_into(weights, &mut deltas);
+        deltas
+    }
+
+    pub fn evaluate_into(&self, weights: &[f32], deltas: &mut MorphDeltas) {
+        deltas.positions.clear();
+        deltas.positions.resize(self.vertices_len, [0.0; 3]);
+        deltas.uvs.clear();
+        deltas.uvs.resize(self.vertices_len, [0.0; 4]);
+        deltas.extended_uvs.resize(self.extended_uv, vec![]);
+        for uvs in &mut deltas.extended_uvs {
+            uvs.clear();
+            uvs.resize(self.vertices_len, [0.0; 4]);
+        }
+        deltas.bones.clear();
+        deltas.bones.resize(self.bones_len, BoneTransform::IDENTITY);
+        deltas.materials.clear();
+        deltas
+            .materials
+            .resize(self.materials_len, MaterialDelta::default());
+        for (i, &weight) in weights.iter().enumerate().take(self.morphs.len()) {
+            if weight != 0.0 {
+                self.apply(i, weight, deltas);
+            }
+        }
+    }
+
+    fn apply(&self, index: usize, weight: f32, deltas: &mut MorphDeltas) {
+        match &self.morphs[index].kind {
+            morph::Kind::Vertex(offsets) => {
+                for o in offsets {
+                    if let Some(p) = deltas.positions.get_mut(o.vertex) {
+                        *p = math::add(*p, math::scale(o.offset, weight));
+                    }
+                }
+            }
+            morph::Kind::Uv(offsets) => add_uvs(&mut deltas.uvs, offsets, weight),
+            morph::Kind::ExtendedUv(ch, offsets) => {
+                if let Some(uvs) = deltas.extended_uvs.get_mut(*ch) {
+                    add_uvs(uvs, offsets, weight);
+                }
+            }
+            morph::Kind::Bone(offsets) => {
+                for o in offsets {
+                    let Some(t) = o.bone.and_then(|b| deltas.bones.get_mut(b)) else {
+                        continue;
+                    };
+                    t.translation = math::add(t.translation, math::scale(o.offset, weight));
+                    let rotation = math::slerp(math::QUAT_IDENTITY, o.rotation, weight);
+                    t.rotation = math::quat_normalize(math::quat_mul(t.rotation, rotation));
+                }
+            }
+            morph::Kind::Material(offsets) => {
+                for o in offsets {
+                    let value = MaterialParams::from_morph(o);
+                    // An absent material index targets every material.
+                    let targets = match o.material {
+                        Some(m) => deltas.materials.get_mut(m..=m).unwrap_or_default(),
+                        None => &mut deltas.materials[..],
+                    };
+                    for delta in targets {
+                        match o.op {
+                            morph::MaterialOp::Mul => {
+                                let mul =
+                                    MaterialParams::ONE.zip(&value, |a, b| a + (b - a) * weight);
+                                delta.mul = delta.mul.zip(&mul, |a, b| a * b);
+                            }
+                            morph::MaterialOp::Add => {
+                                delta.add = delta.add.zip(&value, |a, b| a + b * weight);
+                            }
+                        }
+                    }
+                }
+            }
+            morph::Kind::Group(groups) => {
+                for g in groups {
+                    if let Some(m) = g.morph {
+                        self.apply(m, weight * g.ratio, deltas);
+                    }
+                }
+            }
+            // The weight selects a single member, which is applied at its own ratio.
+            morph::Kind::Flip(flips) => {
+                if weight <= 0.0 || flips.is_empty() {
+                    return;
+                }
+                let n = flips.len();
+                let i = (((n + 1) as f32 * weight) as usize).clamp(1, n) - 1;
+                if let Some(m) = flips[i].morph {
+                    self.apply(m, flips[i].ratio, deltas);
+                }
+            }
+            morph::Kind::Impulse(_) => {}
+        }
+    }
+}
+
+fn add_uvs(uvs: &mut [[f32; 4]], offsets: &[morph::Uv], weight: f32) {
+    for o in offsets {
+        if let Some(uv) = uvs.get_mut(o.vertex) {
+            *uv = std::array::from_fn(|j| uv[j] + o.offset[j] * weight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn morph(kind: morph::Kind) -> Morph {
+        Morph {
+            name: String::new(),
+            name_en: String::new(),
+            panel: Panel::Other,
+            kind,
+        }
+    }
+
+    fn group(members: &[(usize, f32)]) -> Morph {
+        morph(morph::Kind::Group(
+            members
+                .iter()
+                .map(|&(m, ratio)| morph::Group {
+                    morph: Some(m),
+                    ratio,
+                })
+                .collect(),
+        ))
+    }
+
+    fn material(op: morph::MaterialOp, material: Option<usize>, v: f32) -> morph::Material {
+        let p = MaterialParams::splat(v);
+        morph::Material {
+            material,
+            op,
+            diffuse: p.diffuse,
+            specular: p.specular,
+            specular_power: p.specular_power,
+            ambient: p.ambient,
+            edge_color: p.edge_color,
+            edge_size: p.edge_size,
+            texture: p.texture,
+            sphere: p.sphere,
+            toon: p.toon,
+        }
+    }
+
+    #[test]
+    fn vertex_and_group() {
+        let morphs = vec![
+            morph(morph::Kind::Vertex(vec![morph::Vertex {
+                vertex: 1,
+                offset: [1.0, 2.0, 0.0],
+            }])),
+            morph(morph::Kind::ExtendedUv(
+                0,
+                vec![morph::Uv {
+                    vertex: 0,
+                    offset: [1.0; 4],
+                }],
+            )),
+            group(&[(0, 0.5), (1, 1.0)]),
+        ];
+        let evaluator = MorphEvaluator::new(morphs, 2, 1, 0, 0).unwrap();
+        let deltas = evaluator.evaluate(&[0.5, 0.0, 1.0]);
+        assert!(deltas.positions == [[0.0; 3], [1.0, 2.0, 0.0]]);
+        assert!(deltas.extended_uvs[0][0] == [1.0; 4]);
+        assert!(deltas.uvs[0] == [0.0; 4]);
+    }
+
+    #[test]
+    fn bone() {
+        let rotation = math::quat_from_axis_angle([0.0, 1.0, 0.0], 1.0);
+        let morphs = vec![morph(morph::Kind::Bone(vec![morph::Bone {
+            bone: Some(1),
+            offset: [0.0, 2.0, 0.0],
+            rotation,
+        }]))];
+        let evaluator = MorphEvaluator::new(morphs, 0, 0, 2, 0).unwrap();
+        let deltas = evaluator.evaluate(&[0.5]);
+        assert!(deltas.bones[0].is_identity());
+        assert!(deltas.bones[1].translation == [0.0, 1.0, 0.0]);
+        let half = math::quat_from_axis_angle([0.0, 1.0, 0.0], 0.5);
+        assert!((0..4).all(|i| (deltas.bones[1].rotation[i] - half[i]).abs() < 1e-5));
+        let mut pose = Pose::new(2, 1);
+        pose.bones[1].translation = [1.0, 0.0, 0.0];
+        deltas.apply_to_pose(&mut pose);
+        assert!(pose.bones[1].translation == [1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn materials() {
+        use morph::MaterialOp::*;
+        let morphs = vec![
+            morph(morph::Kind::Material(vec![material(Mul, Some(1), 0.0)])),
+            morph(morph::Kind::Material(vec![material(Add, None, 0.5)])),
+        ];
+        let evaluator = MorphEvaluator::new(morphs, 0, 0, 0, 2).unwrap();
+        let deltas = evaluator.evaluate(&[0.5, 1.0]);
+        assert!(deltas.materials[0].mul == MaterialParams::ONE);
+        assert!(deltas.materials[1].mul.diffuse == [0.5; 4]);
+        assert!(deltas.materials[0].add.edge_size == 0.5);
+        assert!(deltas.materials[1].add.toon == [0.5; 4]);
+        assert!(evaluator.evaluate(&[0.0, 0.0]).materials[0].is_identity());
+    }
+
+    #[test]
+    fn cycle() {
+        let morphs = vec![group(&[(1, 1.0)]), group(&[(2, 1.0)]), group(&[(0, 1.0)])];
+        let e = MorphEvaluator::new(morphs, 0, 0, 0, 0).unwrap_err();
+        assert!(e.info().unwrap().section == Some(Section::Morphs));
+        let morphs = vec![group(&[(1, 1.0), (1, 0.5)]), group(&[])];
+        assert!(MorphEvaluator::new(morphs, 0, 0, 0, 0).is_ok());
+        assert!(MorphEvaluator::new(vec![group(&[(3, 1.0)])], 0, 0, 0, 0).is_err());
+        let flip = |m| {
+            morph(morph::Kind::Flip(vec![morph::Group {
+                morph: Some(m),
+                ratio: 1.0,
+            }]))
+        };
+        assert!(MorphEvaluator::new(vec![group(&[(1, 1.0)]), flip(0)], 0, 0, 0, 0).is_err());
+        assert!(MorphEvaluator::new(vec![flip(2)], 0, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn flip() {
+        let vertex = |v| {
+            morph(morph::Kind::Vertex(vec![morph::Vertex {
+                vertex: v,
+                offset: [1.0; 3],
+            }]))
+        };
+        let mut morphs = vec![vertex(0), vertex(1), vertex(2)];
+        morphs.push(morph(morph::Kind::Flip(
+            [(0, 1.0), (1, 0.5), (2, 2.0)]
+                .into_iter()
+                .map(|(m, ratio)| morph::Group {
+                    morph: Some(m),
+                    ratio,
+                })
+                .collect(),
+        )));
+        let evaluator = MorphEvaluator::new(morphs, 3, 0, 0, 0).unwrap();
+        let positions = |weight| evaluator.evaluate(&[0.0, 0.0, 0.0, weight]).positions;
+        assert!(positions(0.0) == [[0.0; 3]; 3]);
+        assert!(positions(0.2) == [[1.0; 3], [0.0; 3], [0.0; 3]]);
+        assert!(positions(0.5) == [[0.0; 3], [0.5; 3], [0.0; 3]]);
+        assert!(positions(1.0) == [[0.0; 3], [0.0; 3], [2.0; 3]]);
+    }
+
+    #[test]
+    fn mismatched_deltas() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let mut vertices = model.vertices[..2].to_vec();
+        for v in &mut vertices {
+            v.extended_uv = vec![[0.0; 4]; 2];
+        }
+        let expected = vertices.clone();
+        MorphDeltas::default().apply_to_vertices(&mut vertices);
+        assert!(vertices
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| a.position == b.position && a.extended_uv == b.extended_uv));
+        let deltas = MorphDeltas {
+            positions: vec![[1.0; 3]],
+            extended_uvs: vec![vec![[1.0; 4]; 3]],
+            ..Default::default()
+        };
+        deltas.apply_to_vertices(&mut vertices);
+        assert!(vertices[0].position == math::add(expected[0].position, [1.0; 3]));
+        assert!(vertices[1].position == expected[1].position);
+        assert!(vertices[1].extended_uv == [[1.0; 4], [0.0; 4]]);
+    }
+
+    #[test]
+    fn model() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let evaluator = MorphEvaluator::from_model(&model).unwrap();
+        let mut weights = vec![0.0; model.morphs.len()];
+        let deltas = evaluator.evaluate(&weights);
+        assert!(deltas.positions.iter().all(|p| *p == [0.0; 3]));
+        let i = model
+            .morphs
+            .iter()
+            .position(|m| matches!(&m.kind, morph::Kind::Vertex(v) if !v.is_empty()))
+            .unwrap();
+        weights[i] = 1.0;
+        let deltas = evaluator.evaluate(&weights);
+        let mut vertices = model.vertices.clone();
+        deltas.apply_to_vertices(&mut vertices);
+        let morph::Kind::Vertex(offsets) = &model.morphs[i].kind else {
+            unreachable!()
+        };
+        let o = &offsets[0];
+        let expected = math::add(model.vertices[o.vertex].position, o.offset);
+        assert!(vertices[o.vertex].position == expected);
+    }
+}
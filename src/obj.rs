@@ -0,0 +1,172 @@
+use super::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ObjOptions {
+    // Mirrors Z and reverses the winding to get a right-handed mesh.
+    pub flip_handedness: bool,
+}
+
+fn material_name(index: usize, material: &Material) -> String {
+    let name = material
+        .name
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect::<String>();
+    format!("{index}_{name}")
+}
+
+impl Model {
+    pub fn write_obj<W: Write>(
+        &self,
+        mut writer: W,
+        mtl_name: &str,
+        options: ObjOptions,
+    ) -> Result<W, Error> {
+        let ranges = self.material_ranges()?;
+        let z = if options.flip_handedness { -1.0 } else { 1.0 };
+        writeln!(writer, "# {}", self.name)?;
+        writeln!(writer, "mtllib {mtl_name}")?;
+        for v in &self.vertices {
+            let [x, y, vz] = v.position;
+            writeln!(writer, "v {x:.6} {y:.6} {:.6}", vz * z)?;
+        }
+        for v in &self.vertices {
+            let [u, tv] = v.uv;
+            // OBJ puts the texture origin at the bottom left.
+            writeln!(writer, "vt {u:.6} {:.6}", 1.0 - tv)?;
+        }
+        for v in &self.vertices {
+            let [x, y, nz] = v.normal;
+            writeln!(writer, "vn {x:.6} {y:.6} {:.6}", nz * z)?;
+        }
+        for (i, (material, range)) in self.materials.iter().zip(ranges).enumerate() {
+            let name = material_name(i, material);
+            writeln!(writer, "g {name}")?;
+            writeln!(writer, "usemtl {name}")?;
+            for face in self.faces[range].chunks(3) {
+                let [a, b, c] = [face[0] + 1, face[1] + 1, face[2] + 1];
+                let [b, c] = if options.flip_handedness {
+                    [c, b]
+                } else {
+                    [b, c]
+                };
+                writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+            }
+        }
+        Ok(writer)
+    }
+
+    pub fn write_mtl<W: Write>(&self, mut writer: W) -> Result<W, Error> {
+        for (i, m) in self.materials.iter().enumerate() {
+            let [dr, dg, db, alpha] = m.diffuse;
+            let [sr, sg, sb] = m.specular;
+            let [ar, ag, ab] = m.ambient;
+            writeln!(writer, "newmtl {}", material_name(i, m))?;
+            writeln!(writer, "Kd {dr:.6} {dg:.6} {db:.6}")?;
+            writeln!(writer, "Ks {sr:.6} {sg:.6} {sb:.6}")?;
+            writeln!(writer, "Ka {ar:.6} {ag:.6} {ab:.6}")?;
+            writeln!(writer, "Ns {:.6}", m.specular_power)?;
+            writeln!(writer, "d {alpha:.6}")?;
+            writeln!(writer, "illum 2")?;
+            if let Some(texture) = m.texture.and_then(|t| self.textures.get(t)) {
+                writeln!(
+                    writer,
+                    "map_Kd {}",
+                    texture.to_string_lossy().replace('\\', "/")
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(writer)
+    }
+
+    // Writes the OBJ to `path` and its materials next to it with a `.mtl` extension.
+    pub fn save_obj(&self, path: impl AsRef<Path>, options: ObjOptions) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mtl = path.with_extension("mtl");
+        let mtl_name = mtl
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file = File::create(path)?;
+        self.write_obj(BufWriter::new(file), &mtl_name, options)?
+            .flush()?;
+        let file = File::create(&mtl)?;
+        self.write_mtl(BufWriter::new(file))?.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(data: &[u8], prefix: &str) -> Vec<String> {
+        String::from_utf8_lossy(data)
+            .lines()
+            .filter(|l| l.starts_with(prefix))
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn obj() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let data = model
+            .write_obj(vec![], "model.mtl", ObjOptions::default())
+            .unwrap();
+        assert!(lines(&data, "mtllib ") == ["mtllib model.mtl"]);
+        assert!(lines(&data, "v ").len() == model.vertices.len());
+        assert!(lines(&data, "vt ").len() == model.vertices.len());
+        assert!(lines(&data, "vn ").len() == model.vertices.len());
+        assert!(lines(&data, "f ").len() * 3 == model.faces.len());
+        assert!(lines(&data, "usemtl ").len() == model.materials.len());
+        let [a, b, c] = [0, 1, 2].map(|i| model.faces[i] + 1);
+        let face = lines(&data, "f ")[0].clone();
+        assert!(face == format!("f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}"));
+
+        let options = ObjOptions {
+            flip_handedness: true,
+        };
+        let flipped = model.write_obj(vec![], "model.mtl", options).unwrap();
+        assert!(lines(&flipped, "f ")[0] == format!("f {a}/{a}/{a} {c}/{c}/{c} {b}/{b}/{b}"));
+        let z = |data: &[u8]| -> f32 {
+            lines(data, "v ")[0]
+                .split(' ')
+                .nth(3)
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        assert!(z(&data) == -z(&flipped));
+    }
+
+    #[test]
+    fn mtl() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let data = model.write_mtl(vec![]).unwrap();
+        assert!(lines(&data, "newmtl ").len() == model.materials.len());
+        let textured = model
+            .materials
+            .iter()
+            .filter(|m| m.texture.is_some())
+            .count();
+        assert!(lines(&data, "map_Kd ").len() == textured);
+        let m = &model.materials[0];
+        let kd = format!(
+            "Kd {:.6} {:.6} {:.6}",
+            m.diffuse[0], m.diffuse[1], m.diffuse[2]
+        );
+        assert!(lines(&data, "Kd ")[0] == kd);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.obj");
+        model.save_obj(&path, ObjOptions::default()).unwrap();
+        assert!(dir.path().join("model.mtl").is_file());
+        let data = std::fs::read(&path).unwrap();
+        assert!(lines(&data, "mtllib ") == ["mtllib model.mtl"]);
+    }
+}
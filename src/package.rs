@@ -0,0 +1,356 @@
+use super::*;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureUsage {
+    Texture,
+    Sphere,
+    Toon,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextureDependency {
+    pub path: PathBuf,
+    pub texture: Option<usize>,
+    pub resolved: Option<PathBuf>,
+    pub materials: Vec<(usize, TextureUsage)>,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct DependencyReport {
+    pub dependencies: Vec<TextureDependency>,
+    pub unused: Vec<usize>,
+}
+
+impl DependencyReport {
+    pub fn new(model: &Model, resolver: &TextureResolver) -> Self {
+        let mut this = Self::default();
+        let mut textures = HashMap::new();
+        let mut shared = HashMap::new();
+        for (i, m) in model.materials.iter().enumerate() {
+            let uses = [
+                (m.texture, TextureUsage::Texture),
+                (m.sphere, TextureUsage::Sphere),
+            ];
+            for (texture, usage) in uses {
+                if let Some(t) = texture.filter(|&t| t < model.textures.len()) {
+                    this.push(&mut textures, t, i, usage, || TextureDependency {
+                        path: model.textures[t].clone(),
+                        texture: Some(t),
+                        resolved: resolver.resolve(&model.textures[t]),
+                        materials: vec![],
+                    });
+                }
+            }
+            match m.toon {
+                Toon::Texture(Some(t)) if t < model.textures.len() => {
+                    this.push(&mut textures, t, i, TextureUsage::Toon, || {
+                        TextureDependency {
+                            path: model.textures[t].clone(),
+                            texture: Some(t),
+                            resolved: resolver.resolve(&model.textures[t]),
+                            materials: vec![],
+                        }
+                    });
+                }
+                Toon::Shared(n) => {
+                    this.push(&mut shared, n, i, TextureUsage::Toon, || {
+                        TextureDependency {
+                            path: PathBuf::from(shared_toon_name(n)),
+                            texture: None,
+                            resolved: resolver.resolve_toon(&m.toon, &model.textures),
+                            materials: vec![],
+                        }
+                    });
+                }
+                Toon::Texture(_) => {}
+            }
+        }
+        this.unused = (0..model.textures.len())
+            .filter(|t| !textures.contains_key(t))
+            .collect();
+        this
+    }
+
+    fn push<K: std::hash::Hash + Eq>(
+        &mut self,
+        indices: &mut HashMap<K, usize>,
+        key: K,
+        material: usize,
+        usage: TextureUsage,
+        f: impl FnOnce() -> TextureDependency,
+    ) {
+        let i = *indices.entry(key).or_insert_with(|| {
+            self.dependencies.push(f());
+            self.dependencies.len() - 1
+        });
+        self.dependencies[i].materials.push((material, usage));
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &TextureDependency> + '_ {
+        self.dependencies.iter().filter(|d| d.resolved.is_none())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PackageFile {
+    pub source: PathBuf,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct Package {
+    pub model: Model,
+    pub files: Vec<PackageFile>,
+}
+
+// Keeps resolved files at their place below the model directory and moves
+// anything outside of it next to the model.
+fn package_path(resolved: &Path, base: &Path) -> PathBuf {
+    match resolved.strip_prefix(base) {
+        Ok(relative)
+            if relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_))) =>
+        {
+            relative.to_path_buf()
+        }
+        _ => resolved.file_name().map(PathBuf::from).unwrap_or_default(),
+    }
+}
+
+// MikuMikuDance expects Windows separators in texture names.
+fn texture_name(path: &Path) -> PathBuf {
+    let parts = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    PathBuf::from(parts.join("\\"))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Flattened files from different directories may share a name, so later ones
+// get a numbered suffix.
+fn unique_path(files: &[PackageFile], path: PathBuf) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let mut candidate = path.clone();
+    let mut n = 1;
+    while files.iter().any(|f| f.path == candidate) {
+        candidate = path.with_file_name(format!("{stem}_{n}{ext}"));
+        n += 1;
+    }
+    candidate
+}
+
+impl Package {
+    pub fn new(model: &Model, report: &DependencyReport, resolver: &TextureResolver) -> Self {
+        let mut model = model.clone();
+        let mut files: Vec<PackageFile> = vec![];
+        for dependency in &report.dependencies {
+            let Some(resolved) = &dependency.resolved else {
+                continue;
+            };
+            let path = match files.iter().find(|f| same_file(&f.source, resolved)) {
+                Some(file) => file.path.clone(),
+                None => {
+                    let path = unique_path(&files, package_path(resolved, resolver.base()));
+                    files.push(PackageFile {
+                        source: resolved.clone(),
+                        path: path.clone(),
+                    });
+                    path
+                }
+            };
+            // The report may come from another model, so stale indices are skipped.
+            if let Some(texture) = dependency.texture.and_then(|t| model.textures.get_mut(t)) {
+                *texture = texture_name(&path);
+            }
+        }
+        Self { model, files }
+    }
+
+    pub fn save(&self, dir: impl AsRef<Path>, model_name: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        self.model.save(dir.join(model_name))?;
+        for file in &self.files {
+            let dest = dir.join(&file.path);
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&file.source, dest)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    pub fn write_zip<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: W,
+        model_name: impl AsRef<Path>,
+    ) -> Result<W, Error> {
+        let entry_name = |path: &Path| {
+            path.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        let options = zip::write::SimpleFileOptions::default();
+        let mut zip = zip::ZipWriter::new(writer);
+        zip.start_file(entry_name(model_name.as_ref()), options)
+            .map_err(std::io::Error::other)?;
+        self.model.write(&mut zip)?;
+        for file in &self.files {
+            zip.start_file(entry_name(&file.path), options)
+                .map_err(std::io::Error::other)?;
+            std::io::copy(&mut std::fs::File::open(&file.source)?, &mut zip)?;
+        }
+        Ok(zip.finish().map_err(std::io::Error::other)?)
+    }
+
+    #[cfg(feature = "zip")]
+    pub fn save_zip(
+        &self,
+        path: impl AsRef<Path>,
+        model_name: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        let file = std::fs::File::create(path)?;
+        let mut writer = self.write_zip(std::io::BufWriter::new(file), model_name)?;
+        std::io::Write::flush(&mut writer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load() -> (Model, TextureResolver) {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let resolver = TextureResolver::from_model_path("assets/Alicia/Alicia_solid.pmx");
+        (model, resolver)
+    }
+
+    #[test]
+    fn report() {
+        let (mut model, resolver) = load();
+        model.textures.push(PathBuf::from("unused.png"));
+        model.materials[0].toon = Toon::Shared(2);
+        model.materials[1].toon = Toon::Shared(2);
+        let report = DependencyReport::new(&model, &resolver);
+        assert!(report.unused == [model.textures.len() - 1]);
+        let toon = report
+            .dependencies
+            .iter()
+            .find(|d| d.path == Path::new("toon03.bmp"))
+            .unwrap();
+        assert!(toon.texture.is_none());
+        assert!(toon.materials == [(0, TextureUsage::Toon), (1, TextureUsage::Toon)]);
+        let texture = model.materials[0].texture.unwrap();
+        let dependency = report
+            .dependencies
+            .iter()
+            .find(|d| d.texture == Some(texture))
+            .unwrap();
+        assert!(dependency.materials.contains(&(0, TextureUsage::Texture)));
+        assert!(report
+            .missing()
+            .any(|d| d.path == Path::new("Alicia_hair.tga")));
+        assert!(report.missing().all(|d| d.resolved.is_none()));
+    }
+
+    #[test]
+    fn save() {
+        let (mut model, resolver) = load();
+        model.textures[1] = PathBuf::from("..\\Alicia\\HAIR_S.BMP");
+        let report = DependencyReport::new(&model, &resolver);
+        let package = Package::new(&model, &report, &resolver);
+        assert!(package.model.textures[1] == Path::new("hair_s.bmp"));
+        let dir = tempfile::tempdir().unwrap();
+        package.save(dir.path(), "model.pmx").unwrap();
+        let saved = Model::load(dir.path().join("model.pmx")).unwrap();
+        assert!(saved.textures[1] == Path::new("hair_s.bmp"));
+        for file in &package.files {
+            assert!(dir.path().join(&file.path).is_file());
+        }
+    }
+
+    #[test]
+    fn name_collision() {
+        let (mut model, _) = load();
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["model", "a", "b"] {
+            std::fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        std::fs::write(dir.path().join("a/tex.png"), b"a").unwrap();
+        std::fs::write(dir.path().join("b/tex.png"), b"b").unwrap();
+        model.textures = vec![
+            PathBuf::from("..\\a\\tex.png"),
+            PathBuf::from("..\\b\\tex.png"),
+        ];
+        for m in &mut model.materials {
+            m.texture = None;
+            m.sphere = None;
+        }
+        model.materials[0].texture = Some(0);
+        model.materials[1].texture = Some(1);
+        model.materials[2].sphere = Some(1);
+        let resolver = TextureResolver::new(dir.path().join("model"));
+        let report = DependencyReport::new(&model, &resolver);
+        let package = Package::new(&model, &report, &resolver);
+        assert!(package.model.textures == [Path::new("tex.png"), Path::new("tex_1.png")]);
+        assert!(package.files.len() == 2);
+        let out = dir.path().join("out");
+        package.save(&out, "model.pmx").unwrap();
+        assert!(std::fs::read(out.join("tex.png")).unwrap() == b"a");
+        assert!(std::fs::read(out.join("tex_1.png")).unwrap() == b"b");
+
+        model.textures.clear();
+        let package = Package::new(&model, &report, &resolver);
+        assert!(package.model.textures.is_empty());
+    }
+
+    #[test]
+    fn package_paths() {
+        let base = Path::new("models/a");
+        assert!(package_path(Path::new("models/a/tex/x.png"), base) == Path::new("tex/x.png"));
+        assert!(package_path(Path::new("models/a/../b/x.png"), base) == Path::new("x.png"));
+        assert!(package_path(Path::new("shared/toon01.bmp"), base) == Path::new("toon01.bmp"));
+        assert!(texture_name(Path::new("tex/x.png")) == Path::new("tex\\x.png"));
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip() {
+        let (model, resolver) = load();
+        let report = DependencyReport::new(&model, &resolver);
+        let package = Package::new(&model, &report, &resolver);
+        let data = package
+            .write_zip(std::io::Cursor::new(vec![]), "model.pmx")
+            .unwrap()
+            .into_inner();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        assert!(archive.len() == package.files.len() + 1);
+        let mut entry = archive.by_name("model.pmx").unwrap();
+        let mut pmx = vec![];
+        std::io::Read::read_to_end(&mut entry, &mut pmx).unwrap();
+        assert!(Model::from_reader(&Reader::from_bytes(&pmx).unwrap()).is_ok());
+    }
+}
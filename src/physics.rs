@@ -0,0 +1,346 @@
+use super::*;
+use crate::math::{self, Mat4, Quat, Vec3};
+use rapier3d::na::{Quaternion, UnitQuaternion};
+use rapier3d::prelude::*;
+
+const FIXED_TIME_STEP: f32 = 1.0 / 60.0;
+const MAX_SUBSTEPS: usize = 10;
+
+// MikuMikuDance units are roughly a decimeter.
+const GRAVITY: Vec3 = [0.0, -98.0, 0.0];
+
+const LINEAR_AXES: [JointAxis; 3] = [JointAxis::LinX, JointAxis::LinY, JointAxis::LinZ];
+const ANGULAR_AXES: [JointAxis; 3] = [JointAxis::AngX, JointAxis::AngY, JointAxis::AngZ];
+
+fn euler_to_quat(r: Vec3) -> Quat {
+    let x = math::quat_from_axis_angle([1.0, 0.0, 0.0], r[0]);
+    let y = math::quat_from_axis_angle([0.0, 1.0, 0.0], r[1]);
+    let z = math::quat_from_axis_angle([0.0, 0.0, 1.0], r[2]);
+    math::quat_mul(math::quat_mul(y, x), z)
+}
+
+fn to_isometry(m: &Mat4) -> Isometry<Real> {
+    let column = |c: usize| math::normalize([m[c][0], m[c][1], m[c][2]]);
+    let [x, y, z, w] = math::quat_from_basis(column(0), column(1), column(2));
+    Isometry::from_parts(
+        Translation::new(m[3][0], m[3][1], m[3][2]),
+        UnitQuaternion::new_normalize(Quaternion::new(w, x, y, z)),
+    )
+}
+
+fn from_isometry(iso: &Isometry<Real>) -> Mat4 {
+    let t = iso.translation.vector;
+    let q = iso.rotation;
+    math::from_rotation_translation([q.i, q.j, q.k, q.w], [t.x, t.y, t.z])
+}
+
+// Bullet damping removes a fraction of the velocity per second while rapier
+// uses a coefficient, so convert between the two.
+fn damping(fraction: f32) -> f32 {
+    -(1.0 - fraction.clamp(0.0, 0.999)).ln()
+}
+
+#[derive(Clone, Debug)]
+struct Body {
+    handle: RigidBodyHandle,
+    bone: Option<usize>,
+    method: rigid::Method,
+    bind: Mat4,
+    offset: Mat4,
+    inverse_offset: Mat4,
+}
+
+pub struct Physics {
+    bodies: Vec<Body>,
+    gravity: Vector<Real>,
+    pipeline: PhysicsPipeline,
+    parameters: IntegrationParameters,
+    islands: IslandManager,
+    broad_phase: DefaultBroadPhase,
+    narrow_phase: NarrowPhase,
+    rigid_bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+}
+
+impl Physics {
+    pub fn new(rigids: &[Rigid], joints: &[Joint], evaluator: &PoseEvaluator) -> Self {
+        let skeleton = evaluator.skeleton();
+        let mut rigid_bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let mut bodies = Vec::with_capacity(rigids.len());
+        for rigid in rigids {
+            let bind =
+                math::from_rotation_translation(euler_to_quat(rigid.rotation), rigid.position);
+            let bone = rigid.bone.filter(|&b| b < skeleton.len());
+            let offset = match bone {
+                Some(b) => math::mul(&skeleton.inverse_bind_matrix(b), &bind),
+                None => bind,
+            };
+            let world = match bone {
+                Some(b) => math::mul(&evaluator.world_matrix(b), &offset),
+                None => bind,
+            };
+            let builder = match rigid.method {
+                rigid::Method::Static => RigidBodyBuilder::kinematic_position_based(),
+                _ => RigidBodyBuilder::dynamic(),
+            };
+            let handle = rigid_bodies.insert(
+                builder
+                    .position(to_isometry(&world))
+                    .linear_damping(damping(rigid.dump_translation))
+                    .angular_damping(damping(rigid.dump_rotation)),
+            );
+            let [sx, sy, sz] = rigid.size;
+            let collider = match rigid.shape {
+                rigid::Shape::Sphere => ColliderBuilder::ball(sx),
+                rigid::Shape::Box => ColliderBuilder::cuboid(sx, sy, sz),
+                rigid::Shape::Capsule => ColliderBuilder::capsule_y(sy * 0.5, sx),
+            };
+            // The stored mask has a bit set for each group the rigid collides with.
+            let groups = InteractionGroups::new(
+                Group::from_bits_truncate(1 << rigid.group.min(15)),
+                Group::from_bits_truncate(rigid.non_collision_groups as u32),
+            );
+            let mut collider = collider
+                .collision_groups(groups)
+                .restitution(rigid.repulsive)
+                .friction(rigid.friction);
+            if rigid.method != rigid::Method::Static && rigid.mass > 0.0 {
+                collider = collider.mass(rigid.mass);
+            }
+            colliders.insert_with_parent(collider, handle, &mut rigid_bodies);
+            bodies.push(Body {
+                handle,
+                bone,
+                method: rigid.method,
+                bind,
+                offset,
+                inverse_offset: math::inverse_rigid(&offset),
+            });
+        }
+        let mut impulse_joints = ImpulseJointSet::new();
+        for joint in joints {
+            let [Some(a), Some(b)] = joint.rigids.map(|r| r.and_then(|r| bodies.get(r))) else {
+                continue;
+            };
+            if a.handle == b.handle {
+                continue;
+            }
+            let frame =
+                math::from_rotation_translation(euler_to_quat(joint.rotation), joint.position);
+            // Joint frames are given in bind space like the rigids.
+            let local =
+                |body: &Body| to_isometry(&math::mul(&math::inverse_rigid(&body.bind), &frame));
+            let mut generic = GenericJoint::new(JointAxesMask::empty());
+            generic
+                .set_local_frame1(local(a))
+                .set_local_frame2(local(b));
+            let axes = [
+                (
+                    LINEAR_AXES,
+                    &joint.limit_translation,
+                    joint.spring_translation,
+                ),
+                (ANGULAR_AXES, &joint.limit_rotation, joint.spring_rotation),
+            ];
+            let mut locked = JointAxesMask::empty();
+            for (axes, limit, spring) in axes {
+                for (i, axis) in axes.into_iter().enumerate() {
+                    let (lower, upper) = (limit.lower[i], limit.upper[i]);
+                    // A lower bound above the upper bound leaves the axis free.
+                    if lower == upper {
+                        locked |= axis.into();
+                        continue;
+                    }
+                    if lower < upper {
+                        generic.set_limits(axis, [lower, upper]);
+                    }
+                    if spring[i] != 0.0 {
+                        generic.set_motor_position(axis, 0.0, spring[i], 0.0);
+                    }
+                }
+            }
+            generic.lock_axes(locked);
+            impulse_joints.insert(a.handle, b.handle, generic, true);
+        }
+        Self {
+            bodies,
+            gravity: vector![GRAVITY[0], GRAVITY[1], GRAVITY[2]],
+            pipeline: PhysicsPipeline::new(),
+            parameters: IntegrationParameters {
+                dt: FIXED_TIME_STEP,
+                ..Default::default()
+            },
+            islands: IslandManager::new(),
+            broad_phase: DefaultBroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            rigid_bodies,
+            colliders,
+            impulse_joints,
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+        }
+    }
+
+    pub fn from_model(model: &Model, evaluator: &PoseEvaluator) -> Self {
+        Self::new(&model.rigids, &model.joints, evaluator)
+    }
+
+    pub fn from_reader(reader: &Reader, evaluator: &PoseEvaluator) -> Result<Self, Error> {
+        let rigids = reader.rigids().collect::<Result<Vec<_>, _>>()?;
+        let joints = reader.joints().collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(&rigids, &joints, evaluator))
+    }
+
+    pub fn set_gravity(&mut self, gravity: [f32; 3]) {
+        self.gravity = vector![gravity[0], gravity[1], gravity[2]];
+    }
+
+    pub fn rigid_matrix(&self, index: usize) -> Option<[[f32; 4]; 4]> {
+        let body = self.bodies.get(index)?;
+        Some(from_isometry(self.rigid_bodies[body.handle].position()))
+    }
+
+    // Moves every rigid to its bone and clears velocities, e.g. after a seek.
+    pub fn reset(&mut self, evaluator: &PoseEvaluator) {
+        for body in &self.bodies {
+            let Some(bone) = body.bone else {
+                continue;
+            };
+            let world = to_isometry(&math::mul(&evaluator.world_matrix(bone), &body.offset));
+            let rb = &mut self.rigid_bodies[body.handle];
+            rb.set_position(world, true);
+            rb.set_linvel(Vector::zeros(), true);
+            rb.set_angvel(Vector::zeros(), true);
+        }
+    }
+
+    // Call between `update_before_physics` and `update_after_physics`.
+    pub fn step(&mut self, dt: f32, evaluator: &mut PoseEvaluator) {
+        for body in &self.bodies {
+            if let (rigid::Method::Static, Some(bone)) = (body.method, body.bone) {
+                let world = math::mul(&evaluator.world_matrix(bone), &body.offset);
+                self.rigid_bodies[body.handle].set_next_kinematic_position(to_isometry(&world));
+            }
+        }
+        let substeps = ((dt / FIXED_TIME_STEP).ceil() as usize).clamp(1, MAX_SUBSTEPS);
+        self.parameters.dt = dt / substeps as f32;
+        for _ in 0..substeps {
+            self.pipeline.step(
+                &self.gravity,
+                &self.parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.rigid_bodies,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                &mut self.ccd_solver,
+                None,
+                &(),
+                &(),
+            );
+        }
+        let mut worlds = vec![];
+        for body in &self.bodies {
+            let Some(bone) = body.bone else {
+                continue;
+            };
+            let rigid = from_isometry(self.rigid_bodies[body.handle].position());
+            let mut world = math::mul(&rigid, &body.inverse_offset);
+            match body.method {
+                rigid::Method::Static => continue,
+                rigid::Method::Dynamic => {}
+                // Only the rotation is simulated and the bone keeps its position.
+                rigid::Method::DynamicWithBone => world[3] = evaluator.world_matrix(bone)[3],
+            }
+            worlds.push((bone, world));
+        }
+        evaluator.set_world_matrices(&worlds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn euler() {
+        let q = euler_to_quat([0.3, 0.0, 0.0]);
+        let x = math::quat_from_axis_angle([1.0, 0.0, 0.0], 0.3);
+        assert!((0..4).all(|i| (q[i] - x[i]).abs() < 1e-6));
+        let m = math::from_rotation_translation(euler_to_quat([0.1, 0.2, 0.3]), [1.0, 2.0, 3.0]);
+        let m2 = from_isometry(&to_isometry(&m));
+        assert!((0..4).all(|c| (0..4).all(|r| (m[c][r] - m2[c][r]).abs() < 1e-5)));
+    }
+
+    #[test]
+    fn simulate() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let skeleton = Skeleton::from_model(&model).unwrap();
+        let mut evaluator = PoseEvaluator::new(skeleton);
+        let mut physics = Physics::from_model(&model, &evaluator);
+        let rest = evaluator.world_matrices();
+        let pose = Pose::new(model.bones.len(), model.morphs.len());
+        for _ in 0..30 {
+            evaluator.set_pose(&pose);
+            evaluator.update_before_physics();
+            physics.step(FIXED_TIME_STEP, &mut evaluator);
+            evaluator.update_after_physics();
+        }
+        let worlds = evaluator.world_matrices();
+        for (i, rigid) in model.rigids.iter().enumerate() {
+            let Some(bone) = rigid.bone else {
+                continue;
+            };
+            let p = physics.rigid_matrix(i).unwrap();
+            assert!(p.iter().flatten().all(|v| v.is_finite()));
+            if rigid.method == rigid::Method::Static {
+                assert!(worlds[bone] == rest[bone]);
+            }
+        }
+        let moved = model
+            .rigids
+            .iter()
+            .filter(|r| r.method != rigid::Method::Static)
+            .filter_map(|r| r.bone)
+            .any(|b| worlds[b] != rest[b]);
+        assert!(moved);
+        assert!(worlds.iter().flatten().flatten().all(|v| v.is_finite()));
+        physics.reset(&evaluator);
+        let bone = model.rigids[0].bone.unwrap();
+        let p = physics.rigid_matrix(0).unwrap();
+        let expected = math::mul(&evaluator.world_matrix(bone), &physics.bodies[0].offset);
+        assert!((0..3).all(|r| (p[3][r] - expected[3][r]).abs() < 1e-4));
+    }
+
+    #[test]
+    fn posed_joints() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let skeleton = Skeleton::from_model(&model).unwrap();
+        let mut evaluator = PoseEvaluator::new(skeleton);
+        let rest = Physics::from_model(&model, &evaluator);
+        let mut pose = Pose::new(model.bones.len(), model.morphs.len());
+        pose.bones[0].rotation = math::quat_from_axis_angle([0.0, 1.0, 0.0], 1.0);
+        pose.bones[0].translation = [1.0, 2.0, 3.0];
+        evaluator.evaluate(&pose);
+        let posed = Physics::from_model(&model, &evaluator);
+        assert!(!rest.impulse_joints.is_empty());
+        let close = |a: &Isometry<Real>, b: &Isometry<Real>| {
+            (a.translation.vector - b.translation.vector).norm() < 1e-4
+                && a.rotation.angle_to(&b.rotation) < 1e-4
+        };
+        for ((_, a), (_, b)) in rest.impulse_joints.iter().zip(posed.impulse_joints.iter()) {
+            assert!(close(&a.data.local_frame1, &b.data.local_frame1));
+            assert!(close(&a.data.local_frame2, &b.data.local_frame2));
+            // Both bodies still agree on where the joint is.
+            let frame1 = posed.rigid_bodies[b.body1].position() * b.data.local_frame1;
+            let frame2 = posed.rigid_bodies[b.body2].position() * b.data.local_frame2;
+            assert!((frame1.translation.vector - frame2.translation.vector).norm() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,945 @@
+use super::*;
+use crate::binary::{in_elements, BinaryCursor};
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const VERTEX_SIZE: usize = 38;
+const MATERIAL_SIZE: usize = 70;
+const BONE_SIZE: usize = 39;
+const RIGID_SIZE: usize = 83;
+const JOINT_SIZE: usize = 124;
+
+fn optional_index(index: u16) -> Option<usize> {
+    (index != u16::MAX).then_some(index as usize)
+}
+
+struct PmdMaterial {
+    material: Material,
+    texture: String,
+    toon: u8,
+}
+
+struct PmdBone {
+    name: String,
+    parent: Option<usize>,
+    tail: Option<usize>,
+    kind: u8,
+    link: Option<usize>,
+    position: [f32; 3],
+}
+
+struct PmdIk {
+    bone: usize,
+    target: Option<usize>,
+    loop_count: u32,
+    angle: f32,
+    links: Vec<usize>,
+}
+
+struct PmdSkin {
+    name: String,
+    panel: u8,
+    vertices: Vec<(u32, [f32; 3])>,
+}
+
+struct Parser<'a> {
+    data: BinaryCursor<'a>,
+    textures: Vec<PathBuf>,
+}
+
+impl Parser<'_> {
+    fn texture(&mut self, name: &str) -> Option<usize> {
+        if name.is_empty() {
+            return None;
+        }
+        let path = PathBuf::from(name);
+        let index = match self.textures.iter().position(|t| t == &path) {
+            Some(index) => index,
+            None => {
+                self.textures.push(path);
+                self.textures.len() - 1
+            }
+        };
+        Some(index)
+    }
+
+    fn read_vertex(&mut self) -> Result<Vertex, Error> {
+        let data = &mut self.data;
+        let position = data.read_vec::<3>()?;
+        let normal = data.read_vec::<3>()?;
+        let uv = data.read_vec::<2>()?;
+        let bones = [data.read_u16()?, data.read_u16()?];
+        let weight = data.read_u8()?;
+        if weight > 100 {
+            return Err(data.invalid("bone weight", weight));
+        }
+        let no_edge = data.read_u8()?;
+        let weight = match weight {
+            100 => Weight::Bdef1(Bdef1 {
+                bone: optional_index(bones[0]),
+            }),
+            0 => Weight::Bdef1(Bdef1 {
+                bone: optional_index(bones[1]),
+            }),
+            _ => Weight::Bdef2(Bdef2 {
+                bones: bones.map(optional_index),
+                weight: weight as f32 / 100.0,
+            }),
+        };
+        Ok(Vertex {
+            position,
+            normal,
+            uv,
+            extended_uv: vec![],
+            weight,
+            edge_ratio: if no_edge == 0 { 1.0 } else { 0.0 },
+        })
+    }
+
+    fn read_material(&mut self, index: usize) -> Result<PmdMaterial, Error> {
+        let data = &mut self.data;
+        let diffuse = data.read_vec::<4>()?;
+        let specular_power = data.read_f32()?;
+        let specular = data.read_vec::<3>()?;
+        let ambient = data.read_vec::<3>()?;
+        let toon = data.read_u8()?;
+        let edge = data.read_u8()? != 0;
+        let index_count = data.read_u32()?;
+        let texture = data.read_sjis::<20>()?;
+        // MMD disables self shadows for materials with an alpha of exactly 0.98.
+        let self_shadow = diffuse[3] != 0.98;
+        Ok(PmdMaterial {
+            material: Material {
+                name: format!("材質{}", index + 1),
+                name_en: format!("Material{}", index + 1),
+                diffuse,
+                specular,
+                specular_power,
+                ambient,
+                both: diffuse[3] < 1.0,
+                ground_shadow: edge,
+                self_shadow_map: self_shadow,
+                self_shadow,
+                edge,
+                edge_color: [0.0, 0.0, 0.0, 1.0],
+                edge_size: 1.0,
+                texture: None,
+                sphere: None,
+                sphere_mode: SphereMode::None,
+                toon: Toon::Texture(None),
+                memo: String::new(),
+                index_count,
+            },
+            texture,
+            toon,
+        })
+    }
+
+    fn read_bone(&mut self) -> Result<PmdBone, Error> {
+        let data = &mut self.data;
+        let name = data.read_sjis::<20>()?;
+        let parent = optional_index(data.read_u16()?);
+        let tail = match data.read_u16()? {
+            0 | u16::MAX => None,
+            i => Some(i as usize),
+        };
+        let kind = data.read_u8()?;
+        if kind > 9 {
+            return Err(data.invalid("bone type", kind));
+        }
+        let link = match data.read_u16()? {
+            0 | u16::MAX => None,
+            i => Some(i as usize),
+        };
+        let position = data.read_vec::<3>()?;
+        Ok(PmdBone {
+            name,
+            parent,
+            tail,
+            kind,
+            link,
+            position,
+        })
+    }
+
+    fn read_ik(&mut self, bones_len: usize) -> Result<PmdIk, Error> {
+        let bone = self.data.read_u16()? as usize;
+        // Errors point at the IK bone rather than at the position in the IK list.
+        self.read_ik_body(bone)
+            .map_err(|e| e.in_element(Section::Bones, (bone < bones_len).then_some(bone)))
+    }
+
+    fn read_ik_body(&mut self, bone: usize) -> Result<PmdIk, Error> {
+        let data = &mut self.data;
+        let target = optional_index(data.read_u16()?);
+        let len = data.read_u8()? as usize;
+        let loop_count = data.read_u16()? as u32;
+        let angle = data.read_f32()? * 4.0;
+        let links = (0..len)
+            .map(|_| Ok(data.read_u16()? as usize))
+            .collect::<Result<_, Error>>()?;
+        Ok(PmdIk {
+            bone,
+            target,
+            loop_count,
+            angle,
+            links,
+        })
+    }
+
+    fn read_skin(&mut self) -> Result<PmdSkin, Error> {
+        let data = &mut self.data;
+        let name = data.read_sjis::<20>()?;
+        let len = data.read_u32()? as usize;
+        let panel = data.read_u8()?;
+        if panel > 4 {
+            return Err(data.invalid("skin type", panel));
+        }
+        let len = data.read_len(len, 16)?;
+        let vertices = (0..len)
+            .map(|_| Ok((data.read_u32()?, data.read_vec::<3>()?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(PmdSkin {
+            name,
+            panel,
+            vertices,
+        })
+    }
+
+    fn read_rigid(&mut self, bones: &[Bone]) -> Result<Rigid, Error> {
+        let data = &mut self.data;
+        let name = data.read_sjis::<20>()?;
+        let bone = optional_index(data.read_u16()?);
+        let group = data.read_u8()?;
+        let non_collision_groups = data.read_u16()?;
+        let shape = match data.read_u8()? {
+            0 => rigid::Shape::Sphere,
+            1 => rigid::Shape::Box,
+            2 => rigid::Shape::Capsule,
+            v => return Err(data.invalid("rigid shape", v)),
+        };
+        let size = data.read_vec::<3>()?;
+        let offset = data.read_vec::<3>()?;
+        let rotation = data.read_vec::<3>()?;
+        let mass = data.read_f32()?;
+        let dump_translation = data.read_f32()?;
+        let dump_rotation = data.read_f32()?;
+        let repulsive = data.read_f32()?;
+        let friction = data.read_f32()?;
+        let method = match data.read_u8()? {
+            0 => rigid::Method::Static,
+            1 => rigid::Method::Dynamic,
+            2 => rigid::Method::DynamicWithBone,
+            v => return Err(data.invalid("rigid method", v)),
+        };
+        // PMD stores rigid positions relative to their bone, or to the first
+        // bone when the rigid is not attached to any.
+        let origin = bones
+            .get(bone.unwrap_or(0))
+            .map_or([0.0; 3], |b| b.position);
+        Ok(Rigid {
+            name,
+            name_en: String::new(),
+            bone,
+            group,
+            non_collision_groups,
+            shape,
+            size,
+            position: [0, 1, 2].map(|i| origin[i] + offset[i]),
+            rotation,
+            mass,
+            dump_translation,
+            dump_rotation,
+            repulsive,
+            friction,
+            method,
+        })
+    }
+
+    fn read_joint(&mut self) -> Result<Joint, Error> {
+        let data = &mut self.data;
+        let name = data.read_sjis::<20>()?;
+        let rigids =
+            [data.read_u32()?, data.read_u32()?].map(|i| (i != u32::MAX).then_some(i as usize));
+        let position = data.read_vec::<3>()?;
+        let rotation = data.read_vec::<3>()?;
+        let limit_translation = AngleLimit {
+            lower: data.read_vec::<3>()?,
+            upper: data.read_vec::<3>()?,
+        };
+        let limit_rotation = AngleLimit {
+            lower: data.read_vec::<3>()?,
+            upper: data.read_vec::<3>()?,
+        };
+        let spring_translation = data.read_vec::<3>()?;
+        let spring_rotation = data.read_vec::<3>()?;
+        Ok(Joint {
+            name,
+            name_en: String::new(),
+            kind: joint::Kind::Spring6Dof,
+            rigids,
+            position,
+            rotation,
+            limit_translation,
+            limit_rotation,
+            spring_translation,
+            spring_rotation,
+        })
+    }
+
+    fn read_count_u32(&mut self, element_size: usize) -> Result<usize, Error> {
+        let len = self.data.read_u32()? as usize;
+        self.data.read_len(len, element_size)
+    }
+
+    fn read_count_u16(&mut self, element_size: usize) -> Result<usize, Error> {
+        let len = self.data.read_u16()? as usize;
+        self.data.read_len(len, element_size)
+    }
+
+    fn read_count_u8(&mut self, element_size: usize) -> Result<usize, Error> {
+        let len = self.data.read_u8()? as usize;
+        self.data.read_len(len, element_size)
+    }
+
+    fn parse(mut self) -> Result<Model, Error> {
+        if self.data.read_bin::<3>()? != *b"Pmd" {
+            return Err(Error::invalid_header("magic number"));
+        }
+        if self.data.read_f32()? != 1.0 {
+            return Err(Error::UnsupportedVersion);
+        }
+        let name = self.data.read_sjis::<20>()?.trim_end().to_string();
+        let comment = self.data.read_sjis::<256>()?.trim_end().to_string();
+
+        let len = self.read_count_u32(VERTEX_SIZE)?;
+        let vertices = in_elements(Section::Vertices, len, |_| self.read_vertex())?;
+
+        let len = self.read_count_u32(2)?;
+        let faces = in_elements(Section::Faces, len, |_| {
+            let index = self.data.read_u16()?;
+            if index as usize >= vertices.len() {
+                return Err(self.data.invalid("vertex index", index));
+            }
+            Ok(index as usize)
+        })?;
+
+        let len = self.read_count_u32(MATERIAL_SIZE)?;
+        let mut materials = in_elements(Section::Materials, len, |i| self.read_material(i))?;
+
+        let len = self.read_count_u16(BONE_SIZE)?;
+        let pmd_bones = in_elements(Section::Bones, len, |_| self.read_bone())?;
+
+        let len = self.read_count_u16(11)?;
+        let iks = (0..len)
+            .map(|_| {
+                self.read_ik(pmd_bones.len())
+                    .map_err(|e| e.in_element(Section::Bones, None))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let len = self.read_count_u16(25)?;
+        let skins = in_elements(Section::Morphs, len, |_| self.read_skin())?;
+
+        let len = self.read_count_u8(2)?;
+        let skin_list = in_elements(Section::DisplayGroups, len, |_| {
+            Ok(self.data.read_u16()? as usize)
+        })?;
+        let len = self.read_count_u8(50)?;
+        let frame_names =
+            in_elements(Section::DisplayGroups, len, |_| self.data.read_sjis::<50>())?;
+        let len = self.read_count_u32(3)?;
+        let bone_list = in_elements(Section::DisplayGroups, len, |_| {
+            Ok((
+                self.data.read_u16()? as usize,
+                self.data.read_u8()? as usize,
+            ))
+        })?;
+
+        let mut name_en = String::new();
+        let mut comment_en = String::new();
+        let mut bone_names_en = vec![];
+        let mut skin_names_en = vec![];
+        let mut frame_names_en = vec![];
+        if !self.data.is_end() && self.data.read_u8()? == 1 {
+            name_en = self.data.read_sjis::<20>()?;
+            comment_en = self.data.read_sjis::<256>()?;
+            for _ in 0..pmd_bones.len() {
+                bone_names_en.push(self.data.read_sjis::<20>()?);
+            }
+            for _ in 1..skins.len() {
+                skin_names_en.push(self.data.read_sjis::<20>()?);
+            }
+            for _ in 0..frame_names.len() {
+                frame_names_en.push(self.data.read_sjis::<50>()?);
+            }
+        }
+
+        let mut toon_names = (1..=10)
+            .map(|i| format!("toon{i:02}.bmp"))
+            .collect::<Vec<_>>();
+        let default_toon_names = toon_names.clone();
+        if !self.data.is_end() {
+            for name in &mut toon_names {
+                *name = self.data.read_sjis::<100>()?;
+            }
+        }
+
+        let bones = convert_bones(&pmd_bones, &iks, bone_names_en)?;
+
+        let mut rigids = vec![];
+        let mut joints = vec![];
+        if !self.data.is_end() {
+            let len = self.read_count_u32(RIGID_SIZE)?;
+            rigids = in_elements(Section::Rigids, len, |_| self.read_rigid(&bones))?;
+            let len = self.read_count_u32(JOINT_SIZE)?;
+            joints = in_elements(Section::Joints, len, |_| self.read_joint())?;
+        }
+
+        for (i, m) in materials.iter_mut().enumerate() {
+            let (texture, sphere) = match m.texture.split_once('*') {
+                Some((texture, sphere)) => (texture.to_string(), sphere.to_string()),
+                None => {
+                    let lower = m.texture.to_ascii_lowercase();
+                    if lower.ends_with(".sph") || lower.ends_with(".spa") {
+                        (String::new(), m.texture.clone())
+                    } else {
+                        (m.texture.clone(), String::new())
+                    }
+                }
+            };
+            m.material.texture = self.texture(&texture);
+            m.material.sphere = self.texture(&sphere);
+            if m.material.sphere.is_some() {
+                m.material.sphere_mode = if sphere.to_ascii_lowercase().ends_with(".spa") {
+                    SphereMode::Add
+                } else {
+                    SphereMode::Mul
+                };
+            }
+            m.material.toon = match toon_names.get(m.toon as usize) {
+                Some(name) if *name == default_toon_names[m.toon as usize] => Toon::Shared(m.toon),
+                Some(name) => Toon::Texture(self.texture(name)),
+                None if m.toon == u8::MAX => Toon::Texture(None),
+                None => {
+                    return Err(
+                        Error::invalid_data("toon index").in_element(Section::Materials, Some(i))
+                    )
+                }
+            };
+        }
+
+        let morphs = convert_skins(skins, skin_names_en, vertices.len())?;
+        let display_groups = convert_display_groups(
+            &skin_list,
+            frame_names,
+            frame_names_en,
+            &bone_list,
+            bones.len(),
+            morphs.len(),
+        )?;
+
+        let mut model = Model {
+            header: Header {
+                version: 2.0,
+                encoding: Encoding::Utf16,
+                extended_uv: 0,
+                vertex_index_size: 4,
+                texture_index_size: 4,
+                material_index_size: 4,
+                bone_index_size: 4,
+                morph_index_size: 4,
+                rigid_index_size: 4,
+            },
+            name,
+            name_en,
+            comment,
+            comment_en,
+            vertices,
+            faces,
+            textures: self.textures,
+            materials: materials.into_iter().map(|m| m.material).collect(),
+            bones,
+            morphs,
+            display_groups,
+            rigids,
+            joints,
+            soft_bodies: vec![],
+        };
+        model.update_index_sizes();
+        Ok(model)
+    }
+}
+
+fn convert_bones(
+    pmd_bones: &[PmdBone],
+    iks: &[PmdIk],
+    mut names_en: Vec<String>,
+) -> Result<Vec<Bone>, Error> {
+    names_en.resize(pmd_bones.len(), String::new());
+    let check = |index: Option<usize>| match index {
+        Some(i) if i >= pmd_bones.len() => Err(Error::invalid_data(format!("bone index {i}"))),
+        _ => Ok(index),
+    };
+    let mut bones = pmd_bones
+        .iter()
+        .zip(names_en)
+        .enumerate()
+        .map(|(i, (b, name_en))| {
+            let err = |e: Error| e.in_element(Section::Bones, Some(i));
+            let parent = check(b.parent).map_err(err)?;
+            // Co-rotating bones keep the ratio (in percent) in the tail field.
+            let tail = if b.kind == 9 {
+                None
+            } else {
+                check(b.tail).map_err(err)?
+            };
+            let link = check(b.link).map_err(err)?;
+            let mut bone = Bone {
+                name: b.name.clone(),
+                name_en,
+                position: b.position,
+                parent,
+                deform_hierarchy: 0,
+                connected_to: ConnectTo::Bone(tail),
+                rotatable: true,
+                translatable: b.kind == 1 || b.kind == 2,
+                visibility: b.kind != 6 && b.kind != 7,
+                operable: b.kind != 6 && b.kind != 7,
+                ik: None,
+                addition: None,
+                after_physics: false,
+                fixed_pole: None,
+                local_pole: None,
+                external_parent: None,
+            };
+            match b.kind {
+                5 => {
+                    bone.addition = Some(Addition {
+                        rotation: true,
+                        translation: false,
+                        local: false,
+                        bone: link,
+                        ratio: 1.0,
+                    });
+                    bone.operable = false;
+                }
+                9 => {
+                    bone.connected_to = ConnectTo::Offset([0.0; 3]);
+                    bone.addition = Some(Addition {
+                        rotation: true,
+                        translation: false,
+                        local: false,
+                        bone: link,
+                        ratio: b.tail.unwrap_or(0) as f32 / 100.0,
+                    });
+                    bone.operable = false;
+                }
+                8 => {
+                    if let Some(tail) = tail {
+                        let d = [0, 1, 2].map(|j| pmd_bones[tail].position[j] - b.position[j]);
+                        let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+                        if len > 0.0 {
+                            bone.fixed_pole = Some(d.map(|v| v / len));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            Ok(bone)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    for (i, ik) in iks.iter().enumerate() {
+        let err = || {
+            let bone = (ik.bone < bones.len()).then_some(ik.bone);
+            Error::invalid_data(format!("ik {i} bone index")).in_element(Section::Bones, bone)
+        };
+        if ik.bone >= bones.len()
+            || ik.target.is_some_and(|t| t >= bones.len())
+            || ik.links.iter().any(|&l| l >= bones.len())
+        {
+            return Err(err());
+        }
+        let links = ik
+            .links
+            .iter()
+            .map(|&l| IkLink {
+                bone: Some(l),
+                limit: pmd_bones[l].name.contains("ひざ").then(|| AngleLimit {
+                    lower: [-PI, 0.0, 0.0],
+                    upper: [-0.5f32.to_radians(), 0.0, 0.0],
+                }),
+            })
+            .collect();
+        let bone = &mut bones[ik.bone];
+        bone.ik = Some(Ik {
+            target_bone: ik.target,
+            loop_count: ik.loop_count,
+            angle: ik.angle,
+            links,
+        });
+        bone.translatable = true;
+    }
+    Ok(bones)
+}
+
+fn convert_skins(
+    skins: Vec<PmdSkin>,
+    mut names_en: Vec<String>,
+    vertices_len: usize,
+) -> Result<Vec<Morph>, Error> {
+    let mut skins = skins.into_iter();
+    let Some(base) = skins.next() else {
+        return Ok(vec![]);
+    };
+    let base = base
+        .vertices
+        .iter()
+        .map(|&(v, _)| {
+            if v as usize >= vertices_len {
+                return Err(Error::invalid_data(format!("vertex index {v}"))
+                    .in_element(Section::Morphs, Some(0)));
+            }
+            Ok(v as usize)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    names_en.resize(skins.len(), String::new());
+    skins
+        .zip(names_en)
+        .enumerate()
+        .map(|(i, (skin, name_en))| {
+            let offsets = skin
+                .vertices
+                .into_iter()
+                .map(|(v, offset)| {
+                    let vertex = *base
+                        .get(v as usize)
+                        .ok_or_else(|| Error::invalid_data(format!("base skin index {v}")))?;
+                    Ok(morph::Vertex { vertex, offset })
+                })
+                .collect::<Result<_, Error>>()
+                .map_err(|e| e.in_element(Section::Morphs, Some(i + 1)))?;
+            let panel = match skin.panel {
+                1 => Panel::Eyebrow,
+                2 => Panel::Eye,
+                3 => Panel::Mouth,
+                4 => Panel::Other,
+                _ => Panel::Reserved,
+            };
+            Ok(Morph {
+                name: skin.name,
+                name_en,
+                panel,
+                kind: morph::Kind::Vertex(offsets),
+            })
+        })
+        .collect()
+}
+
+fn convert_display_groups(
+    skin_list: &[usize],
+    names: Vec<String>,
+    mut names_en: Vec<String>,
+    bone_list: &[(usize, usize)],
+    bones_len: usize,
+    morphs_len: usize,
+) -> Result<Vec<DisplayGroup>, Error> {
+    let mut groups = vec![
+        DisplayGroup {
+            name: "Root".to_string(),
+            name_en: "Root".to_string(),
+            special: true,
+            elements: if bones_len > 0 {
+                vec![DisplayElement::Bone(Some(0))]
+            } else {
+                vec![]
+            },
+        },
+        DisplayGroup {
+            name: "表情".to_string(),
+            name_en: "Exp".to_string(),
+            special: true,
+            elements: vec![],
+        },
+    ];
+    for &skin in skin_list {
+        if skin == 0 || skin > morphs_len {
+            return Err(Error::invalid_data(format!("skin index {skin}"))
+                .in_element(Section::DisplayGroups, Some(1)));
+        }
+        groups[1]
+            .elements
+            .push(DisplayElement::Morph(Some(skin - 1)));
+    }
+    names_en.resize(names.len(), String::new());
+    for (name, name_en) in names.into_iter().zip(names_en) {
+        groups.push(DisplayGroup {
+            name: name.trim_end().to_string(),
+            name_en: name_en.trim_end().to_string(),
+            special: false,
+            elements: vec![],
+        });
+    }
+    for &(bone, frame) in bone_list {
+        if bone >= bones_len || frame == 0 || frame + 1 >= groups.len() {
+            return Err(Error::invalid_data(format!("bone {bone} in frame {frame}"))
+                .in_element(Section::DisplayGroups, None));
+        }
+        groups[frame + 1]
+            .elements
+            .push(DisplayElement::Bone(Some(bone)));
+    }
+    Ok(groups)
+}
+
+impl Model {
+    pub fn from_pmd<T: Read>(mut reader: T) -> Result<Self, Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Self::from_pmd_bytes(&data)
+    }
+
+    pub fn from_pmd_bytes(data: &[u8]) -> Result<Self, Error> {
+        Parser {
+            data: BinaryCursor::new(data),
+            textures: vec![],
+        }
+        .parse()
+    }
+
+    pub fn load_pmd(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Self::from_pmd(BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sjis<const N: usize>(s: &str) -> [u8; N] {
+        let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode(s);
+        let mut buffer = [0u8; N];
+        buffer[..bytes.len()].copy_from_slice(&bytes);
+        buffer
+    }
+
+    fn f32s(data: &mut Vec<u8>, v: &[f32]) {
+        v.iter().for_each(|v| data.extend(v.to_le_bytes()));
+    }
+
+    fn new_data() -> Vec<u8> {
+        let mut data = b"Pmd".to_vec();
+        f32s(&mut data, &[1.0]);
+        data.extend(sjis::<20>("テスト"));
+        data.extend(sjis::<256>("コメント"));
+        data.extend(3u32.to_le_bytes());
+        for (i, weight) in [100u8, 50, 0].into_iter().enumerate() {
+            f32s(&mut data, &[i as f32, 1.0, 0.0, 0.0, 0.0, -1.0, 0.5, 0.5]);
+            data.extend(0u16.to_le_bytes());
+            data.extend(1u16.to_le_bytes());
+            data.push(weight);
+            data.push(0);
+        }
+        data.extend(3u32.to_le_bytes());
+        [0u16, 1, 2]
+            .iter()
+            .for_each(|i| data.extend(i.to_le_bytes()));
+        data.extend(1u32.to_le_bytes());
+        f32s(
+            &mut data,
+            &[1.0, 1.0, 1.0, 0.98, 5.0, 0.1, 0.1, 0.1, 0.5, 0.5, 0.5],
+        );
+        data.push(0);
+        data.push(1);
+        data.extend(3u32.to_le_bytes());
+        data.extend(sjis::<20>("body.bmp*body.spa"));
+        data.extend(3u16.to_le_bytes());
+        for (name, parent, tail, kind) in [
+            ("センター", u16::MAX, 1u16, 1u8),
+            ("左ひざ", 0, 2, 0),
+            ("左足ＩＫ", 0, 0, 2),
+        ] {
+            data.extend(sjis::<20>(name));
+            data.extend(parent.to_le_bytes());
+            data.extend(tail.to_le_bytes());
+            data.push(kind);
+            data.extend(0u16.to_le_bytes());
+            f32s(&mut data, &[0.0, kind as f32, 0.0]);
+        }
+        data.extend(1u16.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.push(1);
+        data.extend(40u16.to_le_bytes());
+        f32s(&mut data, &[0.5]);
+        data.extend(1u16.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        data.extend(sjis::<20>("base"));
+        data.extend(2u32.to_le_bytes());
+        data.push(0);
+        for i in [1u32, 2] {
+            data.extend(i.to_le_bytes());
+            f32s(&mut data, &[i as f32, 1.0, 0.0]);
+        }
+        data.extend(sjis::<20>("あ"));
+        data.extend(1u32.to_le_bytes());
+        data.push(3);
+        data.extend(1u32.to_le_bytes());
+        f32s(&mut data, &[0.0, 0.5, 0.0]);
+        data.push(1);
+        data.extend(1u16.to_le_bytes());
+        data.push(1);
+        data.extend(sjis::<50>("足\n"));
+        data.extend(1u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.push(1);
+        data.push(1);
+        data.extend(sjis::<20>("test"));
+        data.extend(sjis::<256>("comment"));
+        for name in ["center", "knee_L", "leg IK_L"] {
+            data.extend(sjis::<20>(name));
+        }
+        data.extend(sjis::<20>("a"));
+        data.extend(sjis::<50>("Legs"));
+        for i in 1..=10 {
+            data.extend(sjis::<100>(&format!("toon{i:02}.bmp")));
+        }
+        data.extend(1u32.to_le_bytes());
+        data.extend(sjis::<20>("剛体"));
+        data.extend(1u16.to_le_bytes());
+        data.push(2);
+        data.extend(0xfffeu16.to_le_bytes());
+        data.push(2);
+        f32s(&mut data, &[0.5, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+        f32s(&mut data, &[1.0, 0.5, 0.5, 0.0, 0.5]);
+        data.push(1);
+        data.extend(1u32.to_le_bytes());
+        data.extend(sjis::<20>("ジョイント"));
+        data.extend(0u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        f32s(&mut data, &[0.0; 24]);
+        data
+    }
+
+    #[test]
+    fn load() {
+        let model = Model::from_pmd_bytes(&new_data()).unwrap();
+        assert!(model.name == "テスト");
+        assert!(model.name_en == "test");
+        assert!(model.comment == "コメント");
+        assert!(model.vertices.len() == 3);
+        assert!(matches!(
+            model.vertices[0].weight,
+            Weight::Bdef1(Bdef1 { bone: Some(0) })
+        ));
+        assert!(matches!(model.vertices[1].weight, Weight::Bdef2(ref w) if w.weight == 0.5));
+        assert!(matches!(
+            model.vertices[2].weight,
+            Weight::Bdef1(Bdef1 { bone: Some(1) })
+        ));
+        assert!(model.faces == [0, 1, 2]);
+        assert!(model.textures == [PathBuf::from("body.bmp"), PathBuf::from("body.spa")]);
+        let material = &model.materials[0];
+        assert!(material.texture == Some(0));
+        assert!(material.sphere == Some(1));
+        assert!(material.sphere_mode == SphereMode::Add);
+        assert!(matches!(material.toon, Toon::Shared(0)));
+        assert!(!material.self_shadow);
+        assert!(model.bones.len() == 3);
+        assert!(model.bones[1].name == "左ひざ");
+        assert!(model.bones[1].name_en == "knee_L");
+        let ik = model.bones[2].ik.as_ref().unwrap();
+        assert!(ik.target_bone == Some(1));
+        assert!(ik.loop_count == 40);
+        assert!(ik.angle == 2.0);
+        assert!(ik.links[0].bone == Some(1));
+        assert!(ik.links[0].limit.is_some());
+        let morph::Kind::Vertex(offsets) = &model.morphs[0].kind else {
+            panic!();
+        };
+        assert!(model.morphs.len() == 1);
+        assert!(model.morphs[0].panel == Panel::Mouth);
+        assert!(offsets[0].vertex == 2);
+        assert!(model.display_groups.len() == 3);
+        assert!(matches!(
+            model.display_groups[1].elements[0],
+            DisplayElement::Morph(Some(0))
+        ));
+        assert!(model.display_groups[2].name_en == "Legs");
+        assert!(matches!(
+            model.display_groups[2].elements[0],
+            DisplayElement::Bone(Some(1))
+        ));
+        assert!(model.rigids[0].position == [0.0, 1.0, 0.0]);
+        assert!(model.joints[0].rigids == [Some(0), Some(0)]);
+        assert!(model.header.bone_index_size == 1);
+    }
+
+    #[test]
+    fn write() {
+        let model = Model::from_pmd_bytes(&new_data()).unwrap();
+        let data = model.write(vec![]).unwrap();
+        let reader = Reader::from_bytes(&data).unwrap();
+        assert!(reader.name().unwrap() == "テスト");
+        assert!(reader.bones().len() == 3);
+    }
+
+    fn bone_offset(data: &[u8], name: &str) -> usize {
+        let name = sjis::<20>(name);
+        data.windows(20).position(|w| w == name).unwrap() + 20
+    }
+
+    #[test]
+    fn co_rotating() {
+        let mut data = new_data();
+        let offset = bone_offset(&data, "左ひざ");
+        data[offset + 2..offset + 4].copy_from_slice(&100u16.to_le_bytes());
+        data[offset + 4] = 9;
+        let model = Model::from_pmd_bytes(&data).unwrap();
+        let bone = &model.bones[1];
+        assert!(matches!(bone.connected_to, ConnectTo::Offset(_)));
+        assert!(bone.addition.as_ref().unwrap().ratio == 1.0);
+    }
+
+    #[test]
+    fn invalid_ik() {
+        let mut data = new_data();
+        // The IK list follows the last bone.
+        let offset = bone_offset(&data, "左足ＩＫ") + 19;
+        data[offset + 4..offset + 6].copy_from_slice(&7u16.to_le_bytes());
+        let e = Model::from_pmd_bytes(&data).unwrap_err();
+        let info = e.info().unwrap();
+        assert!(info.section == Some(Section::Bones));
+        assert!(info.index == Some(2));
+    }
+
+    #[test]
+    fn truncated_ik() {
+        let mut data = new_data();
+        let offset = bone_offset(&data, "左足ＩＫ") + 19;
+        data[offset + 6] = 255;
+        let e = Model::from_pmd_bytes(&data[..offset + 17]).unwrap_err();
+        assert!(matches!(e, Error::UnexpectedEof(_)));
+        let info = e.info().unwrap();
+        assert!(info.section == Some(Section::Bones));
+        assert!(info.index == Some(2));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = new_data();
+        for len in [0, 100, 300, 500] {
+            assert!(Model::from_pmd_bytes(&data[..len]).is_err());
+        }
+        let mut data = new_data();
+        data[323] = 200;
+        let e = Model::from_pmd_bytes(&data).unwrap_err();
+        let info = e.info().unwrap();
+        assert!(info.section == Some(Section::Vertices));
+        assert!(info.index == Some(0));
+        assert!(info.value == Some(200));
+    }
+}
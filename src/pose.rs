@@ -0,0 +1,42 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoneTransform {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+impl BoneTransform {
+    pub const IDENTITY: Self = Self {
+        translation: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+}
+
+impl Default for BoneTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Pose {
+    pub bones: Vec<BoneTransform>,
+    pub morphs: Vec<f32>,
+}
+
+impl Pose {
+    pub fn new(bones_len: usize, morphs_len: usize) -> Self {
+        Self {
+            bones: vec![BoneTransform::IDENTITY; bones_len],
+            morphs: vec![0.0; morphs_len],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bones.fill(BoneTransform::IDENTITY);
+        self.morphs.fill(0.0);
+    }
+}
@@ -1,108 +1,138 @@
 use super::*;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::{Cursor, Read};
 use std::path::PathBuf;
 
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("unsupported version")]
-    UnsupportedVersion,
-    #[error("invalid header: {0}")]
-    InvalidHeader(String),
-    #[error("invalid data: {0}")]
-    InvalidData(String),
-    #[error("io error: {0}")]
-    Io(std::io::Error),
+struct Seeker<'a> {
+    reader: &'a mut Cursor<&'a [u8]>,
+    section: Section,
+    index: Option<usize>,
+    last: u64,
 }
 
-impl Error {
-    pub(crate) fn invalid_header(msg: impl Into<String>) -> Self {
-        Self::InvalidHeader(msg.into())
+impl<'a> Seeker<'a> {
+    fn new(reader: &'a mut Cursor<&'a [u8]>) -> Self {
+        Self {
+            reader,
+            section: Section::Info,
+            index: None,
+            last: 0,
+        }
     }
 
-    pub(crate) fn invalid_data(msg: impl Into<String>) -> Self {
-        Self::InvalidData(msg.into())
+    fn position(&self) -> u64 {
+        self.reader.position()
     }
-}
 
-impl From<std::io::Error> for Error {
-    fn from(value: std::io::Error) -> Self {
-        Self::Io(value)
+    fn enter(&mut self, section: Section) {
+        self.section = section;
+        self.index = None;
     }
-}
 
-struct Seeker<'a> {
-    reader: &'a mut Cursor<&'a Vec<u8>>,
-}
-
-impl<'a> Seeker<'a> {
-    fn new(reader: &'a mut Cursor<&'a Vec<u8>>) -> Self {
-        Self { reader }
+    fn invalid(&self, msg: &str, value: impl Into<i64>) -> Error {
+        Error::invalid_value(msg, self.last, value.into()).in_element(self.section, self.index)
     }
 
-    fn position(&self) -> u64 {
-        self.reader.position()
+    fn eof(&self, offset: u64) -> Error {
+        Error::unexpected_eof(offset).in_element(self.section, self.index)
     }
 
     fn seek_bin(&mut self, len: u64) -> Result<u64, Error> {
         let first = self.position();
-        self.reader.seek(SeekFrom::Current(len as i64))?;
+        let end = first
+            .checked_add(len)
+            .filter(|&end| end <= self.reader.get_ref().len() as u64)
+            .ok_or_else(|| self.eof(first))?;
+        self.reader.set_position(end);
         Ok(first)
     }
 
     fn seek_string(&mut self) -> Result<u64, Error> {
         let first = self.position();
-        let mut buffer = [0u8; 4];
-        self.reader.read_exact(&mut buffer)?;
-        let len = u32::from_le_bytes(buffer);
-        self.reader.seek(SeekFrom::Current(len as i64))?;
+        let len = self.read_u32()?;
+        self.seek_bin(len as u64)?;
         Ok(first)
     }
 
+    fn is_end(&self) -> bool {
+        self.position() >= self.reader.get_ref().len() as u64
+    }
+
     fn read_u8(&mut self) -> Result<u8, Error> {
         let mut buffer = [0u8; 1];
-        self.reader.read_exact(&mut buffer)?;
+        self.last = self.position();
+        if self.reader.read_exact(&mut buffer).is_err() {
+            return Err(self.eof(self.last));
+        }
         Ok(buffer[0])
     }
 
     fn read_u16(&mut self) -> Result<u16, Error> {
         let mut buffer = [0u8; 2];
-        self.reader.read_exact(&mut buffer)?;
+        self.last = self.position();
+        if self.reader.read_exact(&mut buffer).is_err() {
+            return Err(self.eof(self.last));
+        }
         Ok(u16::from_le_bytes(buffer))
     }
 
     fn read_u32(&mut self) -> Result<u32, Error> {
         let mut buffer = [0u8; 4];
-        self.reader.read_exact(&mut buffer)?;
+        self.last = self.position();
+        if self.reader.read_exact(&mut buffer).is_err() {
+            return Err(self.eof(self.last));
+        }
         Ok(u32::from_le_bytes(buffer))
     }
 }
 
+#[derive(Default)]
+struct Span {
+    pos: u64,
+    len: usize,
+    offsets: Vec<u64>,
+}
+
+impl Span {
+    fn new(seeker: &mut Seeker, section: Section) -> Result<(Self, u32), Error> {
+        seeker.enter(section);
+        let len = seeker.read_u32()?;
+        let this = Self {
+            pos: seeker.position(),
+            len: len as usize,
+            offsets: vec![],
+        };
+        Ok((this, len))
+    }
+}
+
 struct Indices {
     name: u64,
     name_en: u64,
     comment: u64,
     comment_en: u64,
-    vertices: u64,
-    faces: u64,
-    textures: u64,
-    materials: u64,
-    bones: u64,
-    morphs: u64,
-    display_groups: u64,
-    rigids: u64,
-    joints: u64,
+    vertices: Span,
+    faces: Span,
+    textures: Span,
+    materials: Span,
+    bones: Span,
+    morphs: Span,
+    display_groups: Span,
+    rigids: Span,
+    joints: Span,
+    soft_bodies: Span,
 }
 
 impl Indices {
-    fn new<'a>(reader: &'a mut Cursor<&'a Vec<u8>>, header: &Header) -> Result<Self, Error> {
+    fn new<'a>(reader: &'a mut Cursor<&'a [u8]>, header: &Header) -> Result<Self, Error> {
         let mut seeker = Seeker::new(reader);
         let name = seeker.seek_string()?;
         let name_en = seeker.seek_string()?;
         let comment = seeker.seek_string()?;
         let comment_en = seeker.seek_string()?;
-        let vertices = seeker.position();
-        let vertices_len = seeker.read_u32()?;
-        for _ in 0..vertices_len {
+        let (mut vertices, vertices_len) = Span::new(&mut seeker, Section::Vertices)?;
+        for i in 0..vertices_len {
+            seeker.index = Some(i as usize);
+            vertices.offsets.push(seeker.position());
             seeker.seek_bin(4 * 3)?;
             seeker.seek_bin(4 * 3)?;
             seeker.seek_bin(4 * 2)?;
@@ -120,49 +150,54 @@ impl Indices {
                 3 => {
                     seeker.seek_bin(header.bone_index_size * 2 + 4 + 4 * 3 * 3)?;
                 }
-                _ => return Err(Error::invalid_data("vertex weight type")),
+                4 if header.is_v2_1() => {
+                    seeker.seek_bin(header.bone_index_size * 4 + 4 * 4)?;
+                }
+                v => return Err(seeker.invalid("vertex weight type", v)),
             }
             seeker.seek_bin(4)?;
         }
-        let faces = seeker.position();
-        let faces_len = seeker.read_u32()?;
+        let (faces, faces_len) = Span::new(&mut seeker, Section::Faces)?;
         if faces_len % 3 != 0 {
-            return Err(Error::invalid_data("faces"));
+            return Err(seeker.invalid("faces length", faces_len));
         }
         seeker.seek_bin(header.vertex_index_size * faces_len as u64)?;
-        let textures = seeker.position();
-        let textures_len = seeker.read_u32()?;
-        for _ in 0..textures_len {
+        let (mut textures, textures_len) = Span::new(&mut seeker, Section::Textures)?;
+        for i in 0..textures_len {
+            seeker.index = Some(i as usize);
+            textures.offsets.push(seeker.position());
             seeker.seek_string()?;
         }
-        let materials = seeker.position();
-        let materials_len = seeker.read_u32()?;
-        for _ in 0..materials_len {
+        let (mut materials, materials_len) = Span::new(&mut seeker, Section::Materials)?;
+        for i in 0..materials_len {
+            seeker.index = Some(i as usize);
+            materials.offsets.push(seeker.position());
             seeker.seek_string()?;
             seeker.seek_string()?;
             seeker.seek_bin(16 + 12 + 4 + 12 + 1 + 16 + 4 + header.texture_index_size * 2)?;
             let sphere_mode = seeker.read_u8()?;
             if sphere_mode > 3 {
-                return Err(Error::invalid_data("material sphere mode"));
+                return Err(seeker.invalid("material sphere mode", sphere_mode));
             }
             match seeker.read_u8()? {
                 0 => {
-                    seeker.seek_bin(header.texture_index_size.into())?;
+                    seeker.seek_bin(header.texture_index_size)?;
                 }
                 1 => {
                     seeker.seek_bin(1)?;
                 }
-                _ => return Err(Error::invalid_data("material toon flag")),
+                v => return Err(seeker.invalid("material toon flag", v)),
             }
             seeker.seek_string()?;
             seeker.seek_bin(4)?;
         }
-        let bones = seeker.position();
-        let bones_len = seeker.read_u32()?;
-        for _ in 0..bones_len {
+        let (mut bones, bones_len) = Span::new(&mut seeker, Section::Bones)?;
+        for i in 0..bones_len {
+            seeker.index = Some(i as usize);
+            bones.offsets.push(seeker.position());
             seeker.seek_string()?;
             seeker.seek_string()?;
-            seeker.seek_bin(12 + header.bone_index_size as u64 + 4)?;
+            seeker.seek_bin(12 + header.bone_index_size + 4)?;
             let flags = seeker.read_u16()?;
             if flags & 0x0001 == 0 {
                 seeker.seek_bin(12)?;
@@ -193,16 +228,18 @@ impl Indices {
                 }
             }
         }
-        let morphs = seeker.position();
-        let morphs_len = seeker.read_u32()?;
-        for _ in 0..morphs_len {
+        let (mut morphs, morphs_len) = Span::new(&mut seeker, Section::Morphs)?;
+        for i in 0..morphs_len {
+            seeker.index = Some(i as usize);
+            morphs.offsets.push(seeker.position());
             seeker.seek_string()?;
             seeker.seek_string()?;
             let panel = seeker.read_u8()?;
             if panel > 4 {
-                return Err(Error::invalid_data("morph panel"));
+                return Err(seeker.invalid("morph panel", panel));
             }
             let ty = seeker.read_u8()?;
+            let ty_offset = seeker.last;
             let len = seeker.read_u32()?;
             match ty {
                 0 => {
@@ -210,6 +247,16 @@ impl Indices {
                         seeker.seek_bin(header.morph_index_size + 4)?;
                     }
                 }
+                9 if header.is_v2_1() => {
+                    for _ in 0..len {
+                        seeker.seek_bin(header.morph_index_size + 4)?;
+                    }
+                }
+                10 if header.is_v2_1() => {
+                    for _ in 0..len {
+                        seeker.seek_bin(header.rigid_index_size + 1 + 12 + 12)?;
+                    }
+                }
                 1 => {
                     for _ in 0..len {
                         seeker.seek_bin(header.vertex_index_size + 12)?;
@@ -220,7 +267,7 @@ impl Indices {
                         seeker.seek_bin(header.bone_index_size + 12 + 16)?;
                     }
                 }
-                3 | 4 | 5 | 6 | 7 => {
+                3..=7 => {
                     for _ in 0..len {
                         seeker.seek_bin(header.vertex_index_size + 16)?;
                     }
@@ -230,17 +277,22 @@ impl Indices {
                         seeker.seek_bin(header.material_index_size)?;
                         let op = seeker.read_u8()?;
                         if op > 1 {
-                            return Err(Error::invalid_data("morph material op"));
+                            return Err(seeker.invalid("morph material op", op));
                         }
                         seeker.seek_bin(16 + 12 + 4 + 12 + 16 + 4 + 16 + 16 + 16)?;
                     }
                 }
-                _ => return Err(Error::invalid_data("morph type")),
+                _ => {
+                    seeker.last = ty_offset;
+                    return Err(seeker.invalid("morph type", ty));
+                }
             }
         }
-        let display_groups = seeker.position();
-        let display_groups_len = seeker.read_u32()?;
-        for _ in 0..display_groups_len {
+        let (mut display_groups, display_groups_len) =
+            Span::new(&mut seeker, Section::DisplayGroups)?;
+        for i in 0..display_groups_len {
+            seeker.index = Some(i as usize);
+            display_groups.offsets.push(seeker.position());
             seeker.seek_string()?;
             seeker.seek_string()?;
             seeker.seek_bin(1)?;
@@ -254,37 +306,66 @@ impl Indices {
                     1 => {
                         seeker.seek_bin(header.morph_index_size)?;
                     }
-                    _ => return Err(Error::invalid_data("display group element")),
+                    v => return Err(seeker.invalid("display group element", v)),
                 }
             }
         }
-        let rigids = seeker.position();
-        let rigids_len = seeker.read_u32()?;
-        for _ in 0..rigids_len {
+        let (mut rigids, rigids_len) = Span::new(&mut seeker, Section::Rigids)?;
+        for i in 0..rigids_len {
+            seeker.index = Some(i as usize);
+            rigids.offsets.push(seeker.position());
             seeker.seek_string()?;
             seeker.seek_string()?;
             seeker.seek_bin(header.bone_index_size + 1 + 2)?;
             let shape = seeker.read_u8()?;
             if shape > 2 {
-                return Err(Error::invalid_data("rigid shape"));
+                return Err(seeker.invalid("rigid shape", shape));
             }
             seeker.seek_bin(12 + 12 + 12 + 4 + 4 + 4 + 4 + 4)?;
             let method = seeker.read_u8()?;
             if method > 2 {
-                return Err(Error::invalid_data("rigid method"));
+                return Err(seeker.invalid("rigid method", method));
             }
         }
-        let joints = seeker.position();
-        let joints_len = seeker.read_u32()?;
-        for _ in 0..joints_len {
+        let (mut joints, joints_len) = Span::new(&mut seeker, Section::Joints)?;
+        for i in 0..joints_len {
+            seeker.index = Some(i as usize);
+            joints.offsets.push(seeker.position());
             seeker.seek_string()?;
             seeker.seek_string()?;
             let ty = seeker.read_u8()?;
-            if ty != 0 {
-                return Err(Error::invalid_data("joint type"));
+            if ty > 5 || (ty != 0 && !header.is_v2_1()) {
+                return Err(seeker.invalid("joint type", ty));
             }
             seeker.seek_bin(header.rigid_index_size * 2 + 12 * 2 + 12 * 4 + 12 * 2)?;
         }
+        let mut soft_bodies = Span::default();
+        if header.is_v2_1() && !seeker.is_end() {
+            let soft_bodies_len;
+            (soft_bodies, soft_bodies_len) = Span::new(&mut seeker, Section::SoftBodies)?;
+            for i in 0..soft_bodies_len {
+                seeker.index = Some(i as usize);
+                soft_bodies.offsets.push(seeker.position());
+                seeker.seek_string()?;
+                seeker.seek_string()?;
+                let shape = seeker.read_u8()?;
+                if shape > 1 {
+                    return Err(seeker.invalid("soft body shape", shape));
+                }
+                seeker.seek_bin(header.material_index_size + 1 + 2 + 1 + 4 + 4 + 4 + 4)?;
+                let aero_model = seeker.read_u32()?;
+                if aero_model > 4 {
+                    return Err(seeker.invalid("soft body aero model", aero_model));
+                }
+                seeker.seek_bin(4 * 12 + 4 * 6 + 4 * 4 + 4 * 3)?;
+                let anchors_len = seeker.read_u32()?;
+                for _ in 0..anchors_len {
+                    seeker.seek_bin(header.rigid_index_size + header.vertex_index_size + 1)?;
+                }
+                let pins_len = seeker.read_u32()?;
+                seeker.seek_bin(header.vertex_index_size * pins_len as u64)?;
+            }
+        }
         Ok(Self {
             name,
             name_en,
@@ -299,148 +380,166 @@ impl Indices {
             display_groups,
             rigids,
             joints,
+            soft_bodies,
         })
     }
 }
 
 #[derive(Clone)]
 struct DataCursor<'a> {
-    reader: Cursor<&'a Vec<u8>>,
+    reader: Cursor<&'a [u8]>,
     header: &'a Header,
+    last: u64,
 }
 
 impl<'a> DataCursor<'a> {
-    fn new(data: &'a Vec<u8>, header: &'a Header) -> Self {
+    fn new(data: &'a [u8], header: &'a Header) -> Self {
         Self {
             reader: Cursor::new(data),
             header,
+            last: 0,
         }
     }
 
-    fn with_position(data: &'a Vec<u8>, header: &'a Header, pos: SeekFrom) -> Self {
+    fn invalid(&self, msg: &str, value: impl Into<i64>) -> Error {
+        Error::invalid_value(msg, self.last, value.into())
+    }
+
+    fn with_position(data: &'a [u8], header: &'a Header, pos: u64) -> Self {
         let mut this = Self::new(data, header);
-        this.seek(pos).unwrap();
+        this.reader.set_position(pos);
         this
     }
 
-    fn read_bin<const N: usize>(&mut self) -> [u8; N] {
+    fn read_bin<const N: usize>(&mut self) -> Result<[u8; N], Error> {
         let mut buffer = [0u8; N];
-        self.reader.read_exact(&mut buffer).unwrap();
-        buffer
+        self.last = self.reader.position();
+        if self.reader.read_exact(&mut buffer).is_err() {
+            return Err(Error::unexpected_eof(self.last));
+        }
+        Ok(buffer)
     }
 
-    fn read_u8(&mut self) -> u8 {
-        self.read_bin::<1>()[0]
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bin::<1>()?[0])
     }
 
-    fn read_u16(&mut self) -> u16 {
-        u16::from_le_bytes(self.read_bin::<2>())
+    fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_bin::<2>()?))
     }
 
-    fn read_u32(&mut self) -> u32 {
-        u32::from_le_bytes(self.read_bin::<4>())
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_bin::<4>()?))
     }
 
-    fn read_i8(&mut self) -> i8 {
-        i8::from_le_bytes(self.read_bin::<1>())
+    fn read_i8(&mut self) -> Result<i8, Error> {
+        Ok(i8::from_le_bytes(self.read_bin::<1>()?))
     }
 
-    fn read_i16(&mut self) -> i16 {
-        i16::from_le_bytes(self.read_bin::<2>())
+    fn read_i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_le_bytes(self.read_bin::<2>()?))
     }
 
-    fn read_i32(&mut self) -> i32 {
-        i32::from_le_bytes(self.read_bin::<4>())
+    fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.read_bin::<4>()?))
     }
 
-    fn read_f32(&mut self) -> f32 {
-        f32::from_le_bytes(self.read_bin::<4>())
+    fn read_f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_le_bytes(self.read_bin::<4>()?))
     }
 
-    fn read_vec<const N: usize>(&mut self) -> [f32; N] {
+    fn read_vec<const N: usize>(&mut self) -> Result<[f32; N], Error> {
         let mut buffer = [0.0f32; N];
-        let b = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, 4 * N) };
-        self.reader.read_exact(b).unwrap();
-        buffer
+        for v in &mut buffer {
+            *v = self.read_f32()?;
+        }
+        Ok(buffer)
     }
 
-    fn read_vec2(&mut self) -> [f32; 2] {
+    fn read_vec2(&mut self) -> Result<[f32; 2], Error> {
         self.read_vec::<2>()
     }
 
-    fn read_vec3(&mut self) -> [f32; 3] {
+    fn read_vec3(&mut self) -> Result<[f32; 3], Error> {
         self.read_vec::<3>()
     }
 
-    fn read_vec4(&mut self) -> [f32; 4] {
+    fn read_vec4(&mut self) -> Result<[f32; 4], Error> {
         self.read_vec::<4>()
     }
 
-    fn read_string(&mut self) -> String {
-        let len = self.read_u32() as usize;
+    fn read_string(&mut self) -> Result<String, Error> {
+        let len = self.read_u32()? as usize;
         if len == 0 {
-            return String::new();
+            return Ok(String::new());
+        }
+        let remaining = (self.reader.get_ref().len() as u64).saturating_sub(self.reader.position());
+        if len as u64 > remaining {
+            return Err(Error::unexpected_eof(self.reader.position()));
         }
         let mut buffer = vec![0u8; len];
-        self.reader.read_exact(&mut buffer).unwrap();
+        self.reader.read_exact(&mut buffer)?;
         match self.header.encoding {
-            Encoding::Utf16 => unsafe {
-                let buffer = std::slice::from_raw_parts(buffer.as_ptr() as *const u16, len / 2);
-                String::from_utf16_lossy(buffer)
-            },
-            Encoding::Utf8 => String::from_utf8_lossy(&buffer).to_string(),
+            Encoding::Utf16 => {
+                if !len.is_multiple_of(2) {
+                    return Err(self.invalid("utf-16 string length", len as i64));
+                }
+                let buffer = buffer
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>();
+                Ok(String::from_utf16_lossy(&buffer))
+            }
+            Encoding::Utf8 => Ok(String::from_utf8_lossy(&buffer).to_string()),
         }
     }
 
-    fn read_signed_index(&mut self, size: u64) -> Option<usize> {
+    fn read_signed_index(&mut self, size: u64) -> Result<Option<usize>, Error> {
         let v = match size {
-            1 => self.read_i8() as i32,
-            2 => self.read_i16() as i32,
-            4 => self.read_i32(),
-            _ => unreachable!(),
+            1 => self.read_i8()? as i32,
+            2 => self.read_i16()? as i32,
+            4 => self.read_i32()?,
+            _ => return Err(Error::invalid_header("index size")),
         };
-        (v >= 0).then_some(v as usize)
+        Ok((v >= 0).then_some(v as usize))
     }
 
-    fn read_vertex_index(&mut self) -> usize {
+    fn read_vertex_index(&mut self) -> Result<usize, Error> {
         match self.header.vertex_index_size {
-            1 => self.read_u8() as usize,
-            2 => self.read_u16() as usize,
-            4 => self.read_i32() as usize,
-            _ => unreachable!(),
+            1 => Ok(self.read_u8()? as usize),
+            2 => Ok(self.read_u16()? as usize),
+            4 => Ok(self.read_i32()? as usize),
+            _ => Err(Error::invalid_header("index size")),
         }
     }
 
-    fn read_texture_index(&mut self) -> Option<usize> {
+    fn read_texture_index(&mut self) -> Result<Option<usize>, Error> {
         self.read_signed_index(self.header.texture_index_size)
     }
 
-    fn read_material_index(&mut self) -> Option<usize> {
+    fn read_material_index(&mut self) -> Result<Option<usize>, Error> {
         self.read_signed_index(self.header.material_index_size)
     }
 
-    fn read_bone_index(&mut self) -> Option<usize> {
+    fn read_bone_index(&mut self) -> Result<Option<usize>, Error> {
         self.read_signed_index(self.header.bone_index_size)
     }
 
-    fn read_morph_index(&mut self) -> Option<usize> {
+    fn read_morph_index(&mut self) -> Result<Option<usize>, Error> {
         self.read_signed_index(self.header.morph_index_size)
     }
 
-    fn read_rigid_index(&mut self) -> Option<usize> {
+    fn read_rigid_index(&mut self) -> Result<Option<usize>, Error> {
         self.read_signed_index(self.header.rigid_index_size)
     }
-
-    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
-        Ok(self.reader.seek(pos)?)
-    }
 }
 
 struct DataIterator<'a, F, R>
 where
-    F: FnMut(&mut DataCursor<'a>) -> R,
+    F: FnMut(&mut DataCursor<'a>) -> Result<R, Error>,
 {
     data: DataCursor<'a>,
+    section: Section,
     current: usize,
     len: usize,
     next: F,
@@ -448,11 +547,12 @@ where
 
 impl<'a, F, R> DataIterator<'a, F, R>
 where
-    F: FnMut(&mut DataCursor<'a>) -> R,
+    F: FnMut(&mut DataCursor<'a>) -> Result<R, Error>,
 {
-    fn new(data: DataCursor<'a>, len: usize, next: F) -> Self {
+    fn new(data: DataCursor<'a>, section: Section, len: usize, next: F) -> Self {
         Self {
             data,
+            section,
             current: 0,
             len,
             next,
@@ -462,43 +562,629 @@ where
 
 impl<'a, F, R> Iterator for DataIterator<'a, F, R>
 where
-    F: FnMut(&mut DataCursor<'a>) -> R,
+    F: FnMut(&mut DataCursor<'a>) -> Result<R, Error>,
 {
-    type Item = R;
+    type Item = Result<R, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.current >= self.len {
             return None;
         }
-        let ret = (self.next)(&mut self.data);
-        self.current += 1;
+        let ret =
+            (self.next)(&mut self.data).map_err(|e| e.in_element(self.section, Some(self.current)));
+        // The cursor position is meaningless after a decode error.
+        self.current = if ret.is_ok() {
+            self.current + 1
+        } else {
+            self.len
+        };
         Some(ret)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len - self.current;
+        (len, Some(len))
+    }
 }
 
-impl<'a, F, R> ExactSizeIterator for DataIterator<'a, F, R>
-where
-    F: FnMut(&mut DataCursor<'a>) -> R,
+impl<'a, F, R> ExactSizeIterator for DataIterator<'a, F, R> where
+    F: FnMut(&mut DataCursor<'a>) -> Result<R, Error>
 {
-    fn len(&self) -> usize {
-        self.len
+}
+
+fn read_texture(data: &mut DataCursor) -> Result<PathBuf, Error> {
+    Ok(data.read_string()?.into())
+}
+
+fn read_vertex(data: &mut DataCursor) -> Result<Vertex, Error> {
+    let position = data.read_vec3()?;
+    let normal = data.read_vec3()?;
+    let uv = data.read_vec2()?;
+    let extended_uv = (0..data.header.extended_uv)
+        .map(|_| data.read_vec4())
+        .collect::<Result<Vec<_>, _>>()?;
+    let weight = match data.read_u8()? {
+        0 => Weight::Bdef1(Bdef1 {
+            bone: data.read_bone_index()?,
+        }),
+        1 => Weight::Bdef2(Bdef2 {
+            bones: [data.read_bone_index()?, data.read_bone_index()?],
+            weight: data.read_f32()?,
+        }),
+        2 => Weight::Bdef4(Bdef4 {
+            bones: [
+                data.read_bone_index()?,
+                data.read_bone_index()?,
+                data.read_bone_index()?,
+                data.read_bone_index()?,
+            ],
+            weights: data.read_vec4()?,
+        }),
+        3 => Weight::Sdef(Sdef {
+            bones: [data.read_bone_index()?, data.read_bone_index()?],
+            weight: data.read_f32()?,
+            c: data.read_vec3()?,
+            r0: data.read_vec3()?,
+            r1: data.read_vec3()?,
+        }),
+        4 => Weight::Qdef(Qdef {
+            bones: [
+                data.read_bone_index()?,
+                data.read_bone_index()?,
+                data.read_bone_index()?,
+                data.read_bone_index()?,
+            ],
+            weights: data.read_vec4()?,
+        }),
+        v => return Err(data.invalid("vertex weight type", v)),
+    };
+    let edge_ratio = data.read_f32()?;
+    Ok(Vertex {
+        position,
+        normal,
+        uv,
+        extended_uv,
+        weight,
+        edge_ratio,
+    })
+}
+
+fn read_material(data: &mut DataCursor) -> Result<Material, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let diffuse = data.read_vec4()?;
+    let specular = data.read_vec3()?;
+    let specular_power = data.read_f32()?;
+    let ambient = data.read_vec3()?;
+    let flags = data.read_u8()?;
+    let both = flags & 0x01 != 0;
+    let ground_shadow = flags & 0x02 != 0;
+    let self_shadow_map = flags & 0x04 != 0;
+    let self_shadow = flags & 0x08 != 0;
+    let edge = flags & 0x10 != 0;
+    let edge_color = data.read_vec4()?;
+    let edge_size = data.read_f32()?;
+    let texture = data.read_texture_index()?;
+    let sphere = data.read_texture_index()?;
+    let sphere_mode = match data.read_u8()? {
+        0 => SphereMode::None,
+        1 => SphereMode::Add,
+        2 => SphereMode::Mul,
+        3 => SphereMode::SubTexture,
+        v => return Err(data.invalid("material sphere mode", v)),
+    };
+    let toon = match data.read_u8()? {
+        0 => Toon::Texture(data.read_texture_index()?),
+        1 => Toon::Shared(data.read_u8()?),
+        v => return Err(data.invalid("material toon flag", v)),
+    };
+    let memo = data.read_string()?;
+    let index_count = data.read_u32()?;
+    Ok(Material {
+        name,
+        name_en,
+        diffuse,
+        specular,
+        specular_power,
+        ambient,
+        both,
+        ground_shadow,
+        self_shadow_map,
+        self_shadow,
+        edge,
+        edge_color,
+        edge_size,
+        texture,
+        sphere,
+        sphere_mode,
+        toon,
+        memo,
+        index_count,
+    })
+}
+
+fn read_bone(data: &mut DataCursor) -> Result<Bone, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let position = data.read_vec3()?;
+    let parent = data.read_bone_index()?;
+    let deform_hierarchy = data.read_i32()?;
+    let flags = data.read_u16()?;
+    let connected_to = flags & 0x0001 != 0;
+    let rotatable = flags & 0x0002 != 0;
+    let translatable = flags & 0x0004 != 0;
+    let visibility = flags & 0x0008 != 0;
+    let operable = flags & 0x0010 != 0;
+    let ik = flags & 0x0020 != 0;
+    let addition_local = flags & 0x0080 != 0;
+    let addition_rotation = flags & 0x0100 != 0;
+    let addition_translation = flags & 0x0200 != 0;
+    let fixed_pole = flags & 0x0400 != 0;
+    let local_pole = flags & 0x0800 != 0;
+    let after_physics = flags & 0x1000 != 0;
+    let external_parent = flags & 0x2000 != 0;
+    let connected_to = if connected_to {
+        ConnectTo::Bone(data.read_bone_index()?)
+    } else {
+        ConnectTo::Offset(data.read_vec3()?)
+    };
+    let addition = if addition_rotation || addition_translation {
+        Some(Addition {
+            rotation: addition_rotation,
+            translation: addition_translation,
+            local: addition_local,
+            bone: data.read_bone_index()?,
+            ratio: data.read_f32()?,
+        })
+    } else {
+        None
+    };
+    let fixed_pole = if fixed_pole {
+        Some(data.read_vec3()?)
+    } else {
+        None
+    };
+    let local_pole = if local_pole {
+        Some(LocalPole {
+            x: data.read_vec3()?,
+            z: data.read_vec3()?,
+        })
+    } else {
+        None
+    };
+    let external_parent = if external_parent {
+        Some(data.read_i32()? as usize)
+    } else {
+        None
+    };
+    let ik = if ik {
+        let target_bone = data.read_bone_index()?;
+        let loop_count = data.read_u32()?;
+        let angle = data.read_f32()?;
+        let link_len = data.read_u32()?;
+        let links = (0..link_len)
+            .map(|_| {
+                let bone = data.read_bone_index()?;
+                let limit = if data.read_u8()? == 1 {
+                    Some(AngleLimit {
+                        lower: data.read_vec3()?,
+                        upper: data.read_vec3()?,
+                    })
+                } else {
+                    None
+                };
+                Ok(IkLink { bone, limit })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Some(Ik {
+            target_bone,
+            loop_count,
+            angle,
+            links,
+        })
+    } else {
+        None
+    };
+    Ok(Bone {
+        name,
+        name_en,
+        position,
+        parent,
+        deform_hierarchy,
+        connected_to,
+        rotatable,
+        translatable,
+        visibility,
+        operable,
+        after_physics,
+        ik,
+        addition,
+        fixed_pole,
+        local_pole,
+        external_parent,
+    })
+}
+
+fn read_morph(data: &mut DataCursor) -> Result<Morph, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let panel = match data.read_u8()? {
+        0 => Panel::Reserved,
+        1 => Panel::Eyebrow,
+        2 => Panel::Eye,
+        3 => Panel::Mouth,
+        4 => Panel::Other,
+        v => return Err(data.invalid("morph panel", v)),
+    };
+    let kind = data.read_u8()?;
+    let kind_offset = data.last;
+    let len = data.read_u32()?;
+    let kind = match kind {
+        0 => morph::Kind::Group(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Group {
+                        morph: data.read_morph_index()?,
+                        ratio: data.read_f32()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        1 => morph::Kind::Vertex(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Vertex {
+                        vertex: data.read_vertex_index()?,
+                        offset: data.read_vec3()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        2 => morph::Kind::Bone(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Bone {
+                        bone: data.read_bone_index()?,
+                        offset: data.read_vec3()?,
+                        rotation: data.read_vec4()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        3 => morph::Kind::Uv(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Uv {
+                        vertex: data.read_vertex_index()?,
+                        offset: data.read_vec4()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        v @ 4..=7 => morph::Kind::ExtendedUv(
+            v as usize - 4,
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Uv {
+                        vertex: data.read_vertex_index()?,
+                        offset: data.read_vec4()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        8 => morph::Kind::Material(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Material {
+                        material: data.read_material_index()?,
+                        op: match data.read_u8()? {
+                            0 => morph::MaterialOp::Mul,
+                            1 => morph::MaterialOp::Add,
+                            v => return Err(data.invalid("morph material op", v)),
+                        },
+                        diffuse: data.read_vec4()?,
+                        specular: data.read_vec3()?,
+                        specular_power: data.read_f32()?,
+                        ambient: data.read_vec3()?,
+                        edge_color: data.read_vec4()?,
+                        edge_size: data.read_f32()?,
+                        texture: data.read_vec4()?,
+                        sphere: data.read_vec4()?,
+                        toon: data.read_vec4()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        9 if data.header.is_v2_1() => morph::Kind::Flip(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Group {
+                        morph: data.read_morph_index()?,
+                        ratio: data.read_f32()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        10 if data.header.is_v2_1() => morph::Kind::Impulse(
+            (0..len)
+                .map(|_| {
+                    Ok(morph::Impulse {
+                        rigid: data.read_rigid_index()?,
+                        local: data.read_u8()? != 0,
+                        velocity: data.read_vec3()?,
+                        torque: data.read_vec3()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        v => {
+            data.last = kind_offset;
+            return Err(data.invalid("morph type", v));
+        }
+    };
+    Ok(Morph {
+        name,
+        name_en,
+        panel,
+        kind,
+    })
+}
+
+fn read_display_group(data: &mut DataCursor) -> Result<DisplayGroup, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let special = data.read_u8()? != 0;
+    let len = data.read_u32()?;
+    let elements = (0..len)
+        .map(|_| match data.read_u8()? {
+            0 => Ok(DisplayElement::Bone(data.read_bone_index()?)),
+            1 => Ok(DisplayElement::Morph(data.read_morph_index()?)),
+            v => Err(data.invalid("display group element", v)),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(DisplayGroup {
+        name,
+        name_en,
+        special,
+        elements,
+    })
+}
+
+fn read_rigid(data: &mut DataCursor) -> Result<Rigid, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let bone = data.read_bone_index()?;
+    let group = data.read_u8()?;
+    let non_collision_groups = data.read_u16()?;
+    let shape = match data.read_u8()? {
+        0 => rigid::Shape::Sphere,
+        1 => rigid::Shape::Box,
+        2 => rigid::Shape::Capsule,
+        v => return Err(data.invalid("rigid shape", v)),
+    };
+    let size = data.read_vec3()?;
+    let position = data.read_vec3()?;
+    let rotation = data.read_vec3()?;
+    let mass = data.read_f32()?;
+    let dump_translation = data.read_f32()?;
+    let dump_rotation = data.read_f32()?;
+    let repulsive = data.read_f32()?;
+    let friction = data.read_f32()?;
+    let method = match data.read_u8()? {
+        0 => rigid::Method::Static,
+        1 => rigid::Method::Dynamic,
+        2 => rigid::Method::DynamicWithBone,
+        v => return Err(data.invalid("rigid method", v)),
+    };
+    Ok(Rigid {
+        name,
+        name_en,
+        bone,
+        group,
+        non_collision_groups,
+        shape,
+        size,
+        position,
+        rotation,
+        mass,
+        dump_translation,
+        dump_rotation,
+        repulsive,
+        friction,
+        method,
+    })
+}
+
+fn read_joint(data: &mut DataCursor) -> Result<Joint, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let kind = match data.read_u8()? {
+        0 => joint::Kind::Spring6Dof,
+        1 => joint::Kind::SixDof,
+        2 => joint::Kind::P2P,
+        3 => joint::Kind::ConeTwist,
+        4 => joint::Kind::Slider,
+        5 => joint::Kind::Hinge,
+        v => return Err(data.invalid("joint type", v)),
+    };
+    let rigids = [data.read_rigid_index()?, data.read_rigid_index()?];
+    let position = data.read_vec3()?;
+    let rotation = data.read_vec3()?;
+    let limit_translation = AngleLimit {
+        lower: data.read_vec3()?,
+        upper: data.read_vec3()?,
+    };
+    let limit_rotation = AngleLimit {
+        lower: data.read_vec3()?,
+        upper: data.read_vec3()?,
+    };
+    let spring_translation = data.read_vec3()?;
+    let spring_rotation = data.read_vec3()?;
+    Ok(Joint {
+        name,
+        name_en,
+        kind,
+        rigids,
+        position,
+        rotation,
+        limit_translation,
+        limit_rotation,
+        spring_translation,
+        spring_rotation,
+    })
+}
+
+fn read_soft_body(data: &mut DataCursor) -> Result<SoftBody, Error> {
+    let name = data.read_string()?;
+    let name_en = data.read_string()?;
+    let shape = match data.read_u8()? {
+        0 => soft_body::Shape::TriMesh,
+        1 => soft_body::Shape::Rope,
+        v => return Err(data.invalid("soft body shape", v)),
+    };
+    let material = data.read_material_index()?;
+    let group = data.read_u8()?;
+    let non_collision_groups = data.read_u16()?;
+    let flags = data.read_u8()?;
+    let b_link = flags & 0x01 != 0;
+    let generate_clusters = flags & 0x02 != 0;
+    let link_crossing = flags & 0x04 != 0;
+    let b_link_distance = data.read_i32()?;
+    let cluster_count = data.read_i32()?;
+    let total_mass = data.read_f32()?;
+    let margin = data.read_f32()?;
+    let aero_model = match data.read_i32()? {
+        0 => soft_body::AeroModel::VPoint,
+        1 => soft_body::AeroModel::VTwoSided,
+        2 => soft_body::AeroModel::VOneSided,
+        3 => soft_body::AeroModel::FTwoSided,
+        4 => soft_body::AeroModel::FOneSided,
+        v => return Err(data.invalid("soft body aero model", v)),
+    };
+    let config = soft_body::Config {
+        vcf: data.read_f32()?,
+        dp: data.read_f32()?,
+        dg: data.read_f32()?,
+        lf: data.read_f32()?,
+        pr: data.read_f32()?,
+        vc: data.read_f32()?,
+        df: data.read_f32()?,
+        mt: data.read_f32()?,
+        chr: data.read_f32()?,
+        khr: data.read_f32()?,
+        shr: data.read_f32()?,
+        ahr: data.read_f32()?,
+    };
+    let cluster = soft_body::Cluster {
+        srhr_cl: data.read_f32()?,
+        skhr_cl: data.read_f32()?,
+        sshr_cl: data.read_f32()?,
+        sr_splt_cl: data.read_f32()?,
+        sk_splt_cl: data.read_f32()?,
+        ss_splt_cl: data.read_f32()?,
+    };
+    let iteration = soft_body::Iteration {
+        v_it: data.read_i32()?,
+        p_it: data.read_i32()?,
+        d_it: data.read_i32()?,
+        c_it: data.read_i32()?,
+    };
+    let material_params = soft_body::Material {
+        lst: data.read_f32()?,
+        ast: data.read_f32()?,
+        vst: data.read_f32()?,
+    };
+    let anchors_len = data.read_u32()?;
+    let anchors = (0..anchors_len)
+        .map(|_| {
+            Ok(soft_body::Anchor {
+                rigid: data.read_rigid_index()?,
+                vertex: data.read_vertex_index()?,
+                near_mode: data.read_u8()? != 0,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let pins_len = data.read_u32()?;
+    let pins = (0..pins_len)
+        .map(|_| data.read_vertex_index())
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(SoftBody {
+        name,
+        name_en,
+        shape,
+        material,
+        group,
+        non_collision_groups,
+        b_link,
+        generate_clusters,
+        link_crossing,
+        b_link_distance,
+        cluster_count,
+        total_mass,
+        margin,
+        aero_model,
+        config,
+        cluster,
+        iteration,
+        material_params,
+        anchors,
+        pins,
+    })
+}
+
+enum Data<'a> {
+    Owned(Vec<u8>),
+    Borrowed(&'a [u8]),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::Mmap),
+}
+
+impl std::ops::Deref for Data<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(data) => data,
+            Self::Borrowed(data) => data,
+            #[cfg(feature = "mmap")]
+            Self::Mmap(data) => data,
+        }
     }
 }
 
-pub struct Reader {
-    data: Vec<u8>,
+pub struct Reader<'a> {
+    data: Data<'a>,
     header: Header,
     indices: Indices,
 }
 
-impl Reader {
+impl Reader<'static> {
     pub fn new<T: Read>(mut reader: T) -> Result<Self, Error> {
         let data = {
             let mut buffer = vec![];
             reader.read_to_end(&mut buffer)?;
             buffer
         };
-        let mut reader = Cursor::new(&data);
+        Self::from_data(Data::Owned(data))
+    }
+
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let file = std::fs::File::open(path)?;
+        // The map is only ever read, but the caller has to make sure the file
+        // is not truncated or modified while the reader is alive.
+        let data = unsafe { memmap2::Mmap::map(&file)? };
+        Self::from_data(Data::Mmap(data))
+    }
+}
+
+impl<'a> Reader<'a> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, Error> {
+        Self::from_data(Data::Borrowed(data))
+    }
+
+    fn from_data(data: Data<'a>) -> Result<Self, Error> {
+        let mut reader = Cursor::new(&*data);
         let mut buffer = [0u8; 4];
         reader.read_exact(&mut buffer)?;
         if buffer != [b'P', b'M', b'X', b' '] {
@@ -506,7 +1192,7 @@ impl Reader {
         }
         reader.read_exact(&mut buffer)?;
         let version = f32::from_le_bytes(buffer);
-        if version != 2.0 {
+        if version != 2.0 && version != 2.1 {
             return Err(Error::UnsupportedVersion);
         }
         let mut buffer = [0u8; 1];
@@ -533,6 +1219,7 @@ impl Reader {
             }
         }
         let header = Header {
+            version,
             encoding,
             extended_uv,
             vertex_index_size: buffer[2] as u64,
@@ -555,509 +1242,219 @@ impl Reader {
         &self.header
     }
 
-    #[inline]
-    pub fn name(&self) -> String {
-        let mut data =
-            DataCursor::with_position(&self.data, &self.header, SeekFrom::Start(self.indices.name));
-        data.read_string()
+    fn read_string(&self, pos: u64) -> Result<String, Error> {
+        DataCursor::with_position(&self.data, &self.header, pos)
+            .read_string()
+            .map_err(|e| e.in_element(Section::Info, None))
+    }
+
+    fn iter<'r, F, R>(&'r self, section: Section, span: &Span, f: F) -> DataIterator<'r, F, R>
+    where
+        F: FnMut(&mut DataCursor<'r>) -> Result<R, Error>,
+    {
+        let data = DataCursor::with_position(&self.data, &self.header, span.pos);
+        DataIterator::new(data, section, span.len, f)
+    }
+
+    fn get<'r, F, R>(
+        &'r self,
+        section: Section,
+        span: &Span,
+        index: usize,
+        f: F,
+    ) -> Result<R, Error>
+    where
+        F: FnOnce(&mut DataCursor<'r>) -> Result<R, Error>,
+    {
+        let pos = *span.offsets.get(index).ok_or(Error::OutOfRange {
+            section,
+            index,
+            len: span.len,
+        })?;
+        let mut data = DataCursor::with_position(&self.data, &self.header, pos);
+        f(&mut data).map_err(|e| e.in_element(section, Some(index)))
     }
 
     #[inline]
-    pub fn name_en(&self) -> String {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.name_en),
-        );
-        data.read_string()
+    pub fn name(&self) -> Result<String, Error> {
+        self.read_string(self.indices.name)
     }
 
     #[inline]
-    pub fn comment(&self) -> String {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.comment),
-        );
-        data.read_string()
+    pub fn name_en(&self) -> Result<String, Error> {
+        self.read_string(self.indices.name_en)
     }
 
     #[inline]
-    pub fn comment_en(&self) -> String {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.comment_en),
-        );
-        data.read_string()
+    pub fn comment(&self) -> Result<String, Error> {
+        self.read_string(self.indices.comment)
     }
 
     #[inline]
-    pub fn vertices(&self) -> impl ExactSizeIterator<Item = Vertex> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.vertices),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let position = data.read_vec3();
-            let normal = data.read_vec3();
-            let uv = data.read_vec2();
-            let extended_uv = (0..data.header.extended_uv)
-                .map(|_| data.read_vec4())
-                .collect::<Vec<_>>();
-            let weight = match data.read_u8() {
-                0 => Weight::Bdef1(Bdef1 {
-                    bone: data.read_bone_index(),
-                }),
-                1 => Weight::Bdef2(Bdef2 {
-                    bones: [data.read_bone_index(), data.read_bone_index()],
-                    weight: data.read_f32(),
-                }),
-                2 => Weight::Bdef4(Bdef4 {
-                    bones: [
-                        data.read_bone_index(),
-                        data.read_bone_index(),
-                        data.read_bone_index(),
-                        data.read_bone_index(),
-                    ],
-                    weights: [
-                        data.read_f32(),
-                        data.read_f32(),
-                        data.read_f32(),
-                        data.read_f32(),
-                    ],
-                }),
-                3 => Weight::Sdef(Sdef {
-                    bones: [data.read_bone_index(), data.read_bone_index()],
-                    weight: data.read_f32(),
-                    c: data.read_vec3(),
-                    r0: data.read_vec3(),
-                    r1: data.read_vec3(),
-                }),
-                _ => unreachable!(),
-            };
-            let edge_ratio = data.read_f32();
-            Vertex {
-                position,
-                normal,
-                uv,
-                extended_uv,
-                weight,
-                edge_ratio,
-            }
-        };
-        DataIterator::new(data, len, f)
+    pub fn comment_en(&self) -> Result<String, Error> {
+        self.read_string(self.indices.comment_en)
     }
 
     #[inline]
-    pub fn faces(&self) -> impl ExactSizeIterator<Item = usize> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.faces),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| data.read_vertex_index();
-        DataIterator::new(data, len, f)
+    pub fn vertices(&self) -> impl ExactSizeIterator<Item = Result<Vertex, Error>> + '_ {
+        self.iter(Section::Vertices, &self.indices.vertices, read_vertex)
     }
 
     #[inline]
-    pub fn textures(&self) -> impl ExactSizeIterator<Item = PathBuf> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.textures),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| data.read_string().into();
-        DataIterator::new(data, len, f)
+    pub fn vertex(&self, index: usize) -> Result<Vertex, Error> {
+        self.get(
+            Section::Vertices,
+            &self.indices.vertices,
+            index,
+            read_vertex,
+        )
     }
 
     #[inline]
-    pub fn materials(&self) -> impl ExactSizeIterator<Item = Material> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.materials),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let name = data.read_string();
-            let name_en = data.read_string();
-            let diffuse = data.read_vec4();
-            let specular = data.read_vec3();
-            let specular_power = data.read_f32();
-            let ambient = data.read_vec3();
-            let flags = data.read_u8();
-            let both = flags & 0x01 != 0;
-            let ground_shadow = flags & 0x02 != 0;
-            let self_shadow_map = flags & 0x04 != 0;
-            let self_shadow = flags & 0x08 != 0;
-            let edge = flags & 0x10 != 0;
-            let edge_color = data.read_vec4();
-            let edge_size = data.read_f32();
-            let texture = data.read_texture_index();
-            let sphere = data.read_texture_index();
-            let sphere_mode = match data.read_u8() {
-                0 => SphereMode::None,
-                1 => SphereMode::Add,
-                2 => SphereMode::Mul,
-                3 => SphereMode::SubTexture,
-                _ => unreachable!(),
-            };
-            let toon = match data.read_u8() {
-                0 => Toon::Texture(data.read_texture_index()),
-                1 => Toon::Shared(data.read_u8()),
-                _ => unreachable!(),
-            };
-            let memo = data.read_string();
-            let index_count = data.read_u32();
-            Material {
-                name,
-                name_en,
-                diffuse,
-                specular,
-                specular_power,
-                ambient,
-                both,
-                ground_shadow,
-                self_shadow_map,
-                self_shadow,
-                edge,
-                edge_color,
-                edge_size,
-                texture,
-                sphere,
-                sphere_mode,
-                toon,
-                memo,
-                index_count,
-            }
-        };
-        DataIterator::new(data, len, f)
+    pub fn faces(&self) -> impl ExactSizeIterator<Item = Result<usize, Error>> + '_ {
+        let f = |data: &mut DataCursor| data.read_vertex_index();
+        self.iter(Section::Faces, &self.indices.faces, f)
     }
 
     #[inline]
-    pub fn bones(&self) -> impl ExactSizeIterator<Item = Bone> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.bones),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let name = data.read_string();
-            let name_en = data.read_string();
-            let position = data.read_vec3();
-            let parent = data.read_bone_index();
-            let deform_hierarchy = data.read_i32();
-            let flags = data.read_u16();
-            let connected_to = flags & 0x0001;
-            let rotatable = flags & 0x0002 != 0;
-            let translatable = flags & 0x0004 != 0;
-            let visibility = flags & 0x0008 != 0;
-            let operable = flags & 0x0010 != 0;
-            let ik = flags & 0x0020 != 0;
-            let addition_local = flags & 0x0080 != 0;
-            let addition_rotation = flags & 0x0100 != 0;
-            let addition_translation = flags & 0x0200 != 0;
-            let fixed_pole = flags & 0x0400 != 0;
-            let local_pole = flags & 0x0800 != 0;
-            let after_physics = flags & 0x1000 != 0;
-            let external_parent = flags & 0x2000 != 0;
-            let connected_to = match connected_to {
-                0 => ConnectTo::Offset(data.read_vec3()),
-                1 => ConnectTo::Bone(data.read_bone_index()),
-                _ => unreachable!(),
-            };
-            let addition = (addition_rotation || addition_translation).then(|| Addition {
-                rotation: addition_rotation,
-                translation: addition_translation,
-                local: addition_local,
-                bone: data.read_bone_index(),
-                ratio: data.read_f32(),
-            });
-            let fixed_pole = fixed_pole.then(|| data.read_vec3());
-            let local_pole = local_pole.then(|| LocalPole {
-                x: data.read_vec3(),
-                z: data.read_vec3(),
+    pub fn face(&self, index: usize) -> Result<[usize; 3], Error> {
+        let span = &self.indices.faces;
+        if index >= span.len / 3 {
+            return Err(Error::OutOfRange {
+                section: Section::Faces,
+                index,
+                len: span.len / 3,
             });
-            let external_parent = external_parent.then(|| data.read_i32() as usize);
-            let ik = ik.then(|| {
-                let target_bone = data.read_bone_index();
-                let loop_count = data.read_u32();
-                let angle = data.read_f32();
-                let link_len = data.read_u32();
-                let links = (0..link_len)
-                    .map(|_| {
-                        let bone = data.read_bone_index();
-                        let limit = (data.read_u8() == 1).then(|| AngleLimit {
-                            lower: data.read_vec3(),
-                            upper: data.read_vec3(),
-                        });
-                        IkLink { bone, limit }
-                    })
-                    .collect::<Vec<_>>();
-                Ik {
-                    target_bone,
-                    loop_count,
-                    angle,
-                    links,
-                }
-            });
-            Bone {
-                name,
-                name_en,
-                position,
-                parent,
-                deform_hierarchy,
-                connected_to,
-                rotatable,
-                translatable,
-                visibility,
-                operable,
-                after_physics,
-                ik,
-                addition,
-                fixed_pole,
-                local_pole,
-                external_parent,
-            }
+        }
+        let pos = span.pos + index as u64 * 3 * self.header.vertex_index_size;
+        let mut data = DataCursor::with_position(&self.data, &self.header, pos);
+        let mut read = || -> Result<[usize; 3], Error> {
+            Ok([
+                data.read_vertex_index()?,
+                data.read_vertex_index()?,
+                data.read_vertex_index()?,
+            ])
         };
-        DataIterator::new(data, len, f)
+        read().map_err(|e| e.in_element(Section::Faces, Some(index * 3)))
     }
 
     #[inline]
-    pub fn morphs(&self) -> impl ExactSizeIterator<Item = Morph> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.morphs),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let name = data.read_string();
-            let name_en = data.read_string();
-            let panel = match data.read_u8() {
-                0 => Panel::Reserved,
-                1 => Panel::Eyebrow,
-                2 => Panel::Eye,
-                3 => Panel::Mouth,
-                4 => Panel::Other,
-                _ => unreachable!(),
-            };
-            let kind = data.read_u8();
-            let len = data.read_u32();
-            let kind = match kind {
-                0 => morph::Kind::Group(
-                    (0..len)
-                        .map(|_| morph::Group {
-                            morph: data.read_morph_index(),
-                            ratio: data.read_f32(),
-                        })
-                        .collect::<Vec<_>>(),
-                ),
-                1 => morph::Kind::Vertex(
-                    (0..len)
-                        .map(|_| morph::Vertex {
-                            vertex: data.read_vertex_index(),
-                            offset: data.read_vec3(),
-                        })
-                        .collect::<Vec<_>>(),
-                ),
-                2 => morph::Kind::Bone(
-                    (0..len)
-                        .map(|_| morph::Bone {
-                            bone: data.read_bone_index(),
-                            offset: data.read_vec3(),
-                            rotation: data.read_vec4(),
-                        })
-                        .collect::<Vec<_>>(),
-                ),
-                3 => morph::Kind::Uv(
-                    (0..len)
-                        .map(|_| morph::Uv {
-                            vertex: data.read_vertex_index(),
-                            offset: data.read_vec4(),
-                        })
-                        .collect::<Vec<_>>(),
-                ),
-                v @ (4 | 5 | 6 | 7) => morph::Kind::ExtendedUv(
-                    v as usize - 4,
-                    (0..len)
-                        .map(|_| morph::Uv {
-                            vertex: data.read_vertex_index(),
-                            offset: data.read_vec4(),
-                        })
-                        .collect::<Vec<_>>(),
-                ),
-                8 => morph::Kind::Material(
-                    (0..len)
-                        .map(|_| morph::Material {
-                            material: data.read_material_index(),
-                            op: match data.read_u8() {
-                                0 => morph::MaterialOp::Mul,
-                                1 => morph::MaterialOp::Add,
-                                _ => unreachable!(),
-                            },
-                            diffuse: data.read_vec4(),
-                            specular: data.read_vec3(),
-                            specular_power: data.read_f32(),
-                            ambient: data.read_vec3(),
-                            edge_color: data.read_vec4(),
-                            edge_size: data.read_f32(),
-                            texture: data.read_vec4(),
-                            sphere: data.read_vec4(),
-                            toon: data.read_vec4(),
-                        })
-                        .collect::<Vec<_>>(),
-                ),
-                _ => unreachable!(),
-            };
-            Morph {
-                name,
-                name_en,
-                panel,
-                kind,
-            }
-        };
-        DataIterator::new(data, len, f)
+    pub fn textures(&self) -> impl ExactSizeIterator<Item = Result<PathBuf, Error>> + '_ {
+        self.iter(Section::Textures, &self.indices.textures, read_texture)
     }
 
     #[inline]
-    pub fn display_groups(&self) -> impl ExactSizeIterator<Item = DisplayGroup> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.display_groups),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let name = data.read_string();
-            let name_en = data.read_string();
-            let special = data.read_u8() != 0;
-            let len = data.read_u32();
-            let elements = (0..len)
-                .map(|_| match data.read_u8() {
-                    0 => DisplayElement::Bone(data.read_bone_index()),
-                    1 => DisplayElement::Morph(data.read_morph_index()),
-                    _ => unreachable!(),
-                })
-                .collect::<Vec<_>>();
-            DisplayGroup {
-                name,
-                name_en,
-                special,
-                elements,
-            }
-        };
-        DataIterator::new(data, len, f)
+    pub fn texture(&self, index: usize) -> Result<PathBuf, Error> {
+        self.get(
+            Section::Textures,
+            &self.indices.textures,
+            index,
+            read_texture,
+        )
     }
 
     #[inline]
-    pub fn rigids(&self) -> impl ExactSizeIterator<Item = Rigid> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.rigids),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let name = data.read_string();
-            let name_en = data.read_string();
-            let bone = data.read_bone_index();
-            let group = data.read_u8();
-            let non_collision_groups = data.read_u16();
-            let shape = match data.read_u8() {
-                0 => rigid::Shape::Sphere,
-                1 => rigid::Shape::Box,
-                2 => rigid::Shape::Capsule,
-                _ => unreachable!(),
-            };
-            let size = data.read_vec3();
-            let position = data.read_vec3();
-            let rotation = data.read_vec3();
-            let mass = data.read_f32();
-            let dump_translation = data.read_f32();
-            let dump_rotation = data.read_f32();
-            let repulsive = data.read_f32();
-            let friction = data.read_f32();
-            let method = match data.read_u8() {
-                0 => rigid::Method::Static,
-                1 => rigid::Method::Dynamic,
-                2 => rigid::Method::DynamicWithBone,
-                _ => unreachable!(),
-            };
-            Rigid {
-                name,
-                name_en,
-                bone,
-                group,
-                non_collision_groups,
-                shape,
-                size,
-                position,
-                rotation,
-                mass,
-                dump_translation,
-                dump_rotation,
-                repulsive,
-                friction,
-                method,
-            }
-        };
-        DataIterator::new(data, len, f)
+    pub fn materials(&self) -> impl ExactSizeIterator<Item = Result<Material, Error>> + '_ {
+        self.iter(Section::Materials, &self.indices.materials, read_material)
     }
 
     #[inline]
-    pub fn joints(&self) -> impl ExactSizeIterator<Item = Joint> + '_ {
-        let mut data = DataCursor::with_position(
-            &self.data,
-            &self.header,
-            SeekFrom::Start(self.indices.joints),
-        );
-        let len = data.read_u32() as usize;
-        let f = |data: &mut DataCursor| {
-            let name = data.read_string();
-            let name_en = data.read_string();
-            let ty = data.read_u8();
-            assert!(ty == 0);
-            let rigids = [data.read_rigid_index(), data.read_rigid_index()];
-            let position = data.read_vec3();
-            let rotation = data.read_vec3();
-            let limit_translation = AngleLimit {
-                lower: data.read_vec3(),
-                upper: data.read_vec3(),
-            };
-            let limit_rotation = AngleLimit {
-                lower: data.read_vec3(),
-                upper: data.read_vec3(),
-            };
-            let spring_translation = data.read_vec3();
-            let spring_rotation = data.read_vec3();
-            Joint {
-                name,
-                name_en,
-                rigids,
-                position,
-                rotation,
-                limit_translation,
-                limit_rotation,
-                spring_translation,
-                spring_rotation,
-            }
-        };
-        DataIterator::new(data, len, f)
+    pub fn material(&self, index: usize) -> Result<Material, Error> {
+        self.get(
+            Section::Materials,
+            &self.indices.materials,
+            index,
+            read_material,
+        )
+    }
+
+    #[inline]
+    pub fn bones(&self) -> impl ExactSizeIterator<Item = Result<Bone, Error>> + '_ {
+        self.iter(Section::Bones, &self.indices.bones, read_bone)
+    }
+
+    #[inline]
+    pub fn bone(&self, index: usize) -> Result<Bone, Error> {
+        self.get(Section::Bones, &self.indices.bones, index, read_bone)
+    }
+
+    #[inline]
+    pub fn morphs(&self) -> impl ExactSizeIterator<Item = Result<Morph, Error>> + '_ {
+        self.iter(Section::Morphs, &self.indices.morphs, read_morph)
+    }
+
+    #[inline]
+    pub fn morph(&self, index: usize) -> Result<Morph, Error> {
+        self.get(Section::Morphs, &self.indices.morphs, index, read_morph)
+    }
+
+    #[inline]
+    pub fn display_groups(
+        &self,
+    ) -> impl ExactSizeIterator<Item = Result<DisplayGroup, Error>> + '_ {
+        self.iter(
+            Section::DisplayGroups,
+            &self.indices.display_groups,
+            read_display_group,
+        )
+    }
+
+    #[inline]
+    pub fn display_group(&self, index: usize) -> Result<DisplayGroup, Error> {
+        self.get(
+            Section::DisplayGroups,
+            &self.indices.display_groups,
+            index,
+            read_display_group,
+        )
+    }
+
+    #[inline]
+    pub fn rigids(&self) -> impl ExactSizeIterator<Item = Result<Rigid, Error>> + '_ {
+        self.iter(Section::Rigids, &self.indices.rigids, read_rigid)
+    }
+
+    #[inline]
+    pub fn rigid(&self, index: usize) -> Result<Rigid, Error> {
+        self.get(Section::Rigids, &self.indices.rigids, index, read_rigid)
+    }
+
+    #[inline]
+    pub fn joints(&self) -> impl ExactSizeIterator<Item = Result<Joint, Error>> + '_ {
+        self.iter(Section::Joints, &self.indices.joints, read_joint)
+    }
+
+    #[inline]
+    pub fn joint(&self, index: usize) -> Result<Joint, Error> {
+        self.get(Section::Joints, &self.indices.joints, index, read_joint)
+    }
+
+    #[inline]
+    pub fn soft_bodies(&self) -> impl ExactSizeIterator<Item = Result<SoftBody, Error>> + '_ {
+        self.iter(
+            Section::SoftBodies,
+            &self.indices.soft_bodies,
+            read_soft_body,
+        )
+    }
+
+    #[inline]
+    pub fn soft_body(&self, index: usize) -> Result<SoftBody, Error> {
+        self.get(
+            Section::SoftBodies,
+            &self.indices.soft_bodies,
+            index,
+            read_soft_body,
+        )
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_comparison)]
 mod tests {
     use super::*;
 
-    fn new_reader() -> Reader {
+    fn new_reader() -> Reader<'static> {
         Reader::new(Cursor::new(include_bytes!(
             "../assets/Alicia/Alicia_solid.pmx"
         )))
@@ -1067,7 +1464,7 @@ mod tests {
     #[test]
     fn name() {
         let reader = new_reader();
-        assert!(reader.name() == "アリシア・ソリッド");
+        assert!(reader.name().unwrap() == "アリシア・ソリッド");
     }
 
     #[test]
@@ -1084,7 +1481,7 @@ mod tests {
     #[test]
     fn last_vertex() {
         let reader = new_reader();
-        let vertex = reader.vertices().last().unwrap();
+        let vertex = reader.vertices().last().unwrap().unwrap();
         let Weight::Bdef1(bdef) = vertex.weight else {
             panic!();
         };
@@ -1094,22 +1491,22 @@ mod tests {
     #[test]
     fn last_face() {
         let reader = new_reader();
-        let index = reader.faces().last().unwrap();
+        let index = reader.faces().last().unwrap().unwrap();
         assert!(index == 4382);
     }
 
     #[test]
     fn last_texture() {
         let reader = new_reader();
-        let texture = reader.textures().last().unwrap();
+        let texture = reader.textures().last().unwrap().unwrap();
         assert!(texture.to_string_lossy() == "Alicia_other.tga");
     }
 
     #[test]
     fn last_material() {
         let reader = new_reader();
-        let textures = reader.textures().collect::<Vec<_>>();
-        let material = reader.materials().last().unwrap();
+        let textures = reader.textures().collect::<Result<Vec<_>, _>>().unwrap();
+        let material = reader.materials().last().unwrap().unwrap();
         assert!(material.name == "maegami");
         assert!(textures[material.texture.unwrap()].to_string_lossy() == "Alicia_hair.tga");
         assert!(material.both == true);
@@ -1123,7 +1520,7 @@ mod tests {
     #[test]
     fn last_bone() {
         let reader = new_reader();
-        let bone = reader.bones().last().unwrap();
+        let bone = reader.bones().last().unwrap().unwrap();
         assert!(bone.name == "右足回転");
         assert!(bone.parent == Some(133));
         let addition = bone.addition.as_ref().unwrap();
@@ -1136,7 +1533,7 @@ mod tests {
     #[test]
     fn last_display_group() {
         let reader = new_reader();
-        let display_group = reader.display_groups().last().unwrap();
+        let display_group = reader.display_groups().last().unwrap().unwrap();
         assert!(display_group.name == "その他");
         assert!(display_group.elements.len() == 4);
         let element = display_group.elements.last().unwrap();
@@ -1149,7 +1546,7 @@ mod tests {
     #[test]
     fn last_rigid() {
         let reader = new_reader();
-        let rigid = reader.rigids().last().unwrap();
+        let rigid = reader.rigids().last().unwrap().unwrap();
         assert!(rigid.name == "三つ編み");
         assert!(rigid.shape == rigid::Shape::Capsule);
         assert!(rigid.method == rigid::Method::Static);
@@ -1158,7 +1555,7 @@ mod tests {
     #[test]
     fn last_joint() {
         let reader = new_reader();
-        let joint = reader.joints().last().unwrap();
+        let joint = reader.joints().last().unwrap().unwrap();
         assert!(joint.name == "リボン右");
         let lower_z = joint.limit_rotation.lower[2];
         let upper_z = joint.limit_rotation.upper[2];
@@ -1169,4 +1566,272 @@ mod tests {
         assert!(l / lower_z.abs() <= f32::EPSILON || l / al.abs() <= f32::EPSILON);
         assert!(u / upper_z.abs() <= f32::EPSILON || u / au.abs() <= f32::EPSILON);
     }
+
+    #[test]
+    fn v2_1() {
+        let mut model = Model::from_reader(&new_reader()).unwrap();
+        model.vertices[0].weight = Weight::Qdef(Qdef {
+            bones: [Some(1), Some(2), None, None],
+            weights: [0.25, 0.75, 0.0, 0.0],
+        });
+        model.morphs.push(Morph {
+            name: "flip".to_string(),
+            name_en: String::new(),
+            panel: Panel::Other,
+            kind: morph::Kind::Flip(vec![morph::Group {
+                morph: Some(0),
+                ratio: 1.0,
+            }]),
+        });
+        model.morphs.push(Morph {
+            name: "impulse".to_string(),
+            name_en: String::new(),
+            panel: Panel::Other,
+            kind: morph::Kind::Impulse(vec![morph::Impulse {
+                rigid: Some(3),
+                local: true,
+                velocity: [1.0, 2.0, 3.0],
+                torque: [0.0, 0.5, 0.0],
+            }]),
+        });
+        model.joints[0].kind = joint::Kind::Hinge;
+        assert!(model.write(vec![]).is_err());
+        model.header.version = 2.1;
+        let data = model.write(vec![]).unwrap();
+        let reader = Reader::new(Cursor::new(data)).unwrap();
+        assert!(reader.header().version == 2.1);
+        let Weight::Qdef(qdef) = reader.vertices().next().unwrap().unwrap().weight else {
+            panic!();
+        };
+        assert!(qdef.bones == [Some(1), Some(2), None, None]);
+        assert!(qdef.weights == [0.25, 0.75, 0.0, 0.0]);
+        let morphs = reader.morphs().collect::<Result<Vec<_>, _>>().unwrap();
+        let morph::Kind::Flip(flip) = &morphs[morphs.len() - 2].kind else {
+            panic!();
+        };
+        assert!(flip[0].morph == Some(0));
+        let morph::Kind::Impulse(impulse) = &morphs[morphs.len() - 1].kind else {
+            panic!();
+        };
+        assert!(impulse[0].rigid == Some(3));
+        assert!(impulse[0].local);
+        assert!(impulse[0].velocity == [1.0, 2.0, 3.0]);
+        assert!(reader.joints().next().unwrap().unwrap().kind == joint::Kind::Hinge);
+    }
+
+    #[test]
+    fn soft_body() {
+        let mut model = Model::from_reader(&new_reader()).unwrap();
+        assert!(model.soft_bodies.is_empty());
+        model.soft_bodies.push(SoftBody {
+            name: "skirt".to_string(),
+            name_en: String::new(),
+            shape: soft_body::Shape::TriMesh,
+            material: Some(3),
+            group: 1,
+            non_collision_groups: 0xfffe,
+            b_link: true,
+            generate_clusters: false,
+            link_crossing: true,
+            b_link_distance: 2,
+            cluster_count: 0,
+            total_mass: 1.5,
+            margin: 0.05,
+            aero_model: soft_body::AeroModel::FTwoSided,
+            config: soft_body::Config {
+                vcf: 1.0,
+                dp: 0.0,
+                dg: 0.0,
+                lf: 0.0,
+                pr: 0.0,
+                vc: 0.0,
+                df: 0.2,
+                mt: 0.0,
+                chr: 1.0,
+                khr: 0.1,
+                shr: 1.0,
+                ahr: 0.7,
+            },
+            cluster: soft_body::Cluster {
+                srhr_cl: 0.1,
+                skhr_cl: 1.0,
+                sshr_cl: 0.5,
+                sr_splt_cl: 0.5,
+                sk_splt_cl: 0.5,
+                ss_splt_cl: 0.5,
+            },
+            iteration: soft_body::Iteration {
+                v_it: 0,
+                p_it: 1,
+                d_it: 0,
+                c_it: 4,
+            },
+            material_params: soft_body::Material {
+                lst: 1.0,
+                ast: 1.0,
+                vst: 1.0,
+            },
+            anchors: vec![soft_body::Anchor {
+                rigid: Some(10),
+                vertex: 20,
+                near_mode: true,
+            }],
+            pins: vec![1, 2, 3],
+        });
+        assert!(model.write(vec![]).is_err());
+        model.header.version = 2.1;
+        let data = model.write(vec![]).unwrap();
+        let reader = Reader::new(Cursor::new(data)).unwrap();
+        assert!(reader.soft_bodies().len() == 1);
+        let soft_body = reader.soft_bodies().next().unwrap().unwrap();
+        assert!(soft_body.name == "skirt");
+        assert!(soft_body.material == Some(3));
+        assert!(soft_body.b_link && !soft_body.generate_clusters && soft_body.link_crossing);
+        assert!(soft_body.aero_model == soft_body::AeroModel::FTwoSided);
+        assert!(soft_body.config.ahr == 0.7);
+        assert!(soft_body.iteration.c_it == 4);
+        assert!(soft_body.anchors[0].rigid == Some(10));
+        assert!(soft_body.anchors[0].vertex == 20);
+        assert!(soft_body.pins == [1, 2, 3]);
+    }
+
+    fn decode_all(reader: &Reader) {
+        let _ = reader.name();
+        let _ = reader.name_en();
+        let _ = reader.comment();
+        let _ = reader.comment_en();
+        reader.vertices().for_each(drop);
+        reader.faces().for_each(drop);
+        reader.textures().for_each(drop);
+        reader.materials().for_each(drop);
+        reader.bones().for_each(drop);
+        reader.morphs().for_each(drop);
+        reader.display_groups().for_each(drop);
+        reader.rigids().for_each(drop);
+        reader.joints().for_each(drop);
+        reader.soft_bodies().for_each(drop);
+    }
+
+    #[test]
+    fn truncated() {
+        let data = include_bytes!("../assets/Alicia/Alicia_solid.pmx");
+        for len in (0..data.len()).step_by(data.len() / 97) {
+            assert!(Reader::new(Cursor::new(&data[..len])).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupted() {
+        let data = include_bytes!("../assets/Alicia/Alicia_solid.pmx");
+        let mut seed = 0x2545f491u32;
+        for _ in 0..16 {
+            let mut data = data.to_vec();
+            for _ in 0..8 {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let pos = seed as usize % data.len();
+                data[pos] = (seed >> 24) as u8;
+            }
+            if let Ok(reader) = Reader::new(Cursor::new(data)) {
+                decode_all(&reader);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_utf16_string() {
+        let mut model = Model::from_reader(&new_reader()).unwrap();
+        model.name = "a".to_string();
+        let mut data = model.write(vec![]).unwrap();
+        data[17] = 3;
+        data.insert(21 + 2, 0);
+        let reader = Reader::new(Cursor::new(data)).unwrap();
+        let Err(Error::InvalidData(info)) = reader.name() else {
+            panic!();
+        };
+        assert!(info.section == Some(Section::Info));
+        assert!(info.offset == Some(17));
+        assert!(info.value == Some(3));
+    }
+
+    #[test]
+    fn error_info() {
+        let mut model = Model::from_reader(&new_reader()).unwrap();
+        let index = model.morphs.len() - 1;
+        model.morphs[index].name = "\u{2603}\u{2605}\u{2602}\u{2600}".to_string();
+        model.morphs[index].name_en = String::new();
+        let mut data = model.write(vec![]).unwrap();
+        let marker = [0x03, 0x26, 0x05, 0x26, 0x02, 0x26, 0x00, 0x26];
+        let pos = data.windows(8).position(|w| w == marker).unwrap();
+        let offset = pos + 8 + 4 + 1;
+        data[offset] = 11;
+        let Err(Error::InvalidData(info)) = Reader::new(Cursor::new(data)) else {
+            panic!();
+        };
+        assert!(info.message == "morph type");
+        assert!(info.section == Some(Section::Morphs));
+        assert!(info.index == Some(index));
+        assert!(info.offset == Some(offset as u64));
+        assert!(info.value == Some(11));
+    }
+
+    #[test]
+    fn eof_info() {
+        let data = include_bytes!("../assets/Alicia/Alicia_solid.pmx");
+        let Err(Error::UnexpectedEof(info)) = Reader::new(Cursor::new(&data[..data.len() - 10]))
+        else {
+            panic!();
+        };
+        assert!(info.section == Some(Section::Joints));
+        assert!(info.index == Some(52));
+    }
+
+    #[test]
+    fn random_access() {
+        let reader = new_reader();
+        let bones = reader.bones().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(reader.bone(149).unwrap().name == bones[149].name);
+        assert!(reader.bone(30).unwrap().name == bones[30].name);
+        let vertex = reader.vertex(22310).unwrap();
+        let Weight::Bdef1(bdef) = vertex.weight else {
+            panic!();
+        };
+        assert!(bdef.bone == Some(35));
+        assert!(reader.face(95598 / 3 - 1).unwrap()[2] == 4382);
+        assert!(reader.texture(11).unwrap().to_string_lossy() == "Alicia_other.tga");
+        assert!(reader.material(21).unwrap().name == "maegami");
+        assert!(reader.rigid(78).unwrap().name == "三つ編み");
+        assert!(reader.joint(52).unwrap().name == "リボン右");
+        let morph = reader.morphs().last().unwrap().unwrap();
+        assert!(reader.morph(reader.morphs().len() - 1).unwrap().name == morph.name);
+        assert!(reader.display_group(0).is_ok());
+        assert!(matches!(
+            reader.bone(150),
+            Err(Error::OutOfRange {
+                section: Section::Bones,
+                index: 150,
+                len: 150,
+            })
+        ));
+        assert!(reader.face(95598 / 3).is_err());
+        assert!(reader.soft_body(0).is_err());
+    }
+
+    #[test]
+    fn borrowed() {
+        let data = std::fs::read("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let reader = Reader::from_bytes(&data).unwrap();
+        assert!(reader.name().unwrap() == "アリシア・ソリッド");
+        assert!(reader.vertices().len() == 22311);
+        assert!(Reader::from_bytes(&data[..100]).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap() {
+        let reader = Reader::open_mmap("assets/Alicia/Alicia_solid.pmx").unwrap();
+        assert!(reader.name().unwrap() == "アリシア・ソリッド");
+        assert!(reader.bones().len() == 150);
+    }
 }
@@ -0,0 +1,244 @@
+use super::*;
+use crate::math;
+
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    bones: Vec<Bone>,
+    children: Vec<Vec<usize>>,
+    roots: Vec<usize>,
+    order: Vec<usize>,
+    after_physics: usize,
+}
+
+impl Skeleton {
+    pub fn new(bones: Vec<Bone>) -> Result<Self, Error> {
+        let mut children = vec![vec![]; bones.len()];
+        let mut roots = vec![];
+        for (i, bone) in bones.iter().enumerate() {
+            match bone.parent {
+                Some(parent) if parent >= bones.len() || parent == i => {
+                    return Err(Error::invalid_data(format!("parent {parent}"))
+                        .in_element(Section::Bones, Some(i)));
+                }
+                Some(parent) => children[parent].push(i),
+                None => roots.push(i),
+            }
+        }
+        let mut visited = 0;
+        let mut stack = roots.clone();
+        while let Some(i) = stack.pop() {
+            visited += 1;
+            stack.extend(&children[i]);
+        }
+        if visited != bones.len() {
+            return Err(
+                Error::invalid_data("cyclic bone hierarchy").in_element(Section::Bones, None)
+            );
+        }
+        let mut order = (0..bones.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| (bones[i].after_physics, bones[i].deform_hierarchy));
+        let after_physics = order
+            .iter()
+            .position(|&i| bones[i].after_physics)
+            .unwrap_or(order.len());
+        Ok(Self {
+            bones,
+            children,
+            roots,
+            order,
+            after_physics,
+        })
+    }
+
+    pub fn from_reader(reader: &Reader) -> Result<Self, Error> {
+        Self::new(reader.bones().collect::<Result<_, _>>()?)
+    }
+
+    pub fn from_model(model: &Model) -> Result<Self, Error> {
+        Self::new(model.bones.clone())
+    }
+
+    pub fn bones(&self) -> &[Bone] {
+        &self.bones
+    }
+
+    pub fn bone(&self, index: usize) -> Option<&Bone> {
+        self.bones.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bones.is_empty()
+    }
+
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.bones[index].parent
+    }
+
+    pub fn children(&self, index: usize) -> &[usize] {
+        &self.children[index]
+    }
+
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn is_root(&self, index: usize) -> bool {
+        self.bones[index].parent.is_none()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.bones.iter().position(|b| b.name == name)
+    }
+
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.bones.len());
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        while let Some(i) = stack.pop() {
+            order.push(i);
+            stack.extend(self.children[i].iter().rev());
+        }
+        order
+    }
+
+    pub fn evaluation_order(&self) -> &[usize] {
+        &self.order
+    }
+
+    pub fn before_physics_order(&self) -> &[usize] {
+        &self.order[..self.after_physics]
+    }
+
+    pub fn after_physics_order(&self) -> &[usize] {
+        &self.order[self.after_physics..]
+    }
+
+    pub fn local_offset(&self, index: usize) -> [f32; 3] {
+        let bone = &self.bones[index];
+        match bone.parent {
+            Some(parent) => math::sub(bone.position, self.bones[parent].position),
+            None => bone.position,
+        }
+    }
+
+    pub fn bind_matrix(&self, index: usize) -> [[f32; 4]; 4] {
+        math::translation(self.bones[index].position)
+    }
+
+    pub fn inverse_bind_matrix(&self, index: usize) -> [[f32; 4]; 4] {
+        math::translation(math::scale(self.bones[index].position, -1.0))
+    }
+
+    pub fn bind_matrices(&self) -> Vec<[[f32; 4]; 4]> {
+        (0..self.bones.len()).map(|i| self.bind_matrix(i)).collect()
+    }
+
+    pub fn inverse_bind_matrices(&self) -> Vec<[[f32; 4]; 4]> {
+        (0..self.bones.len())
+            .map(|i| self.inverse_bind_matrix(i))
+            .collect()
+    }
+
+    pub fn local_axes(&self, index: usize) -> Option<[[f32; 3]; 3]> {
+        let pole = self.bones()[index].local_pole.as_ref()?;
+        let x = math::normalize(pole.x);
+        let y = math::normalize(math::cross(pole.z, x));
+        let z = math::cross(x, y);
+        Some([x, y, z])
+    }
+
+    pub fn local_to_model(&self, index: usize, transform: BoneTransform) -> BoneTransform {
+        let Some([x, y, z]) = self.local_axes(index) else {
+            return transform;
+        };
+        let axes = math::quat_from_basis(x, y, z);
+        BoneTransform {
+            translation: math::quat_rotate(axes, transform.translation),
+            rotation: math::quat_mul(
+                math::quat_mul(axes, transform.rotation),
+                math::quat_conjugate(axes),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_skeleton() -> Skeleton {
+        let reader =
+            Reader::from_bytes(include_bytes!("../assets/Alicia/Alicia_solid.pmx")).unwrap();
+        Skeleton::from_reader(&reader).unwrap()
+    }
+
+    #[test]
+    fn hierarchy() {
+        let skeleton = new_skeleton();
+        assert!(!skeleton.roots().is_empty());
+        for &root in skeleton.roots() {
+            assert!(skeleton.is_root(root));
+        }
+        for i in 0..skeleton.len() {
+            for &child in skeleton.children(i) {
+                assert!(skeleton.parent(child) == Some(i));
+            }
+        }
+        let order = skeleton.topological_order();
+        assert!(order.len() == skeleton.len());
+        let mut position = vec![0; skeleton.len()];
+        order.iter().enumerate().for_each(|(p, &i)| position[i] = p);
+        for i in 0..skeleton.len() {
+            if let Some(parent) = skeleton.parent(i) {
+                assert!(position[parent] < position[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn evaluation_order() {
+        let skeleton = new_skeleton();
+        let order = skeleton.evaluation_order();
+        assert!(order.len() == skeleton.len());
+        let key = |&i: &usize| {
+            let bone = skeleton.bone(i).unwrap();
+            (bone.after_physics, bone.deform_hierarchy)
+        };
+        assert!(order.windows(2).all(|w| key(&w[0]) <= key(&w[1])));
+        assert!(skeleton
+            .after_physics_order()
+            .iter()
+            .all(|&i| skeleton.bone(i).unwrap().after_physics));
+        assert!(
+            skeleton.before_physics_order().len() + skeleton.after_physics_order().len()
+                == skeleton.len()
+        );
+    }
+
+    #[test]
+    fn bind_matrices() {
+        let skeleton = new_skeleton();
+        let i = skeleton.children(skeleton.roots()[0])[0];
+        let bone = skeleton.bone(i).unwrap();
+        let [x, y, z] = bone.position;
+        assert!(skeleton.bind_matrix(i)[3] == [x, y, z, 1.0]);
+        assert!(skeleton.inverse_bind_matrix(i)[3] == [-x, -y, -z, 1.0]);
+        let parent = skeleton.bone(bone.parent.unwrap()).unwrap();
+        let offset = skeleton.local_offset(i);
+        assert!((0..3).all(|j| parent.position[j] + offset[j] == bone.position[j]));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut bones = new_skeleton().bones().to_vec();
+        bones[0].parent = Some(bones.len());
+        assert!(Skeleton::new(bones.clone()).is_err());
+        bones[0].parent = Some(1);
+        bones[1].parent = Some(0);
+        let e = Skeleton::new(bones).unwrap_err();
+        assert!(e.info().unwrap().section == Some(Section::Bones));
+    }
+}
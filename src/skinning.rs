@@ -0,0 +1,226 @@
+use super::*;
+use crate::math::{self, Mat4, Quat, Vec3};
+
+#[derive(Clone, Default, Debug)]
+pub struct SkinnedVertices {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+}
+
+fn matrix(matrices: &[Mat4], bone: Option<usize>) -> &Mat4 {
+    bone.and_then(|b| matrices.get(b))
+        .unwrap_or(&math::IDENTITY)
+}
+
+fn linear(
+    matrices: &[Mat4],
+    bones: &[Option<usize>],
+    weights: &[f32],
+    p: Vec3,
+    n: Vec3,
+) -> (Vec3, Vec3) {
+    let mut position = [0.0; 3];
+    let mut normal = [0.0; 3];
+    for (&bone, &weight) in bones.iter().zip(weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        let m = matrix(matrices, bone);
+        position = math::add(position, math::scale(math::transform_point(m, p), weight));
+        normal = math::add(normal, math::scale(math::transform_vector(m, n), weight));
+    }
+    (position, normal)
+}
+
+fn rotation(m: &Mat4) -> Quat {
+    let column = |c: usize| math::normalize([m[c][0], m[c][1], m[c][2]]);
+    math::quat_from_basis(column(0), column(1), column(2))
+}
+
+fn sdef(matrices: &[Mat4], sdef: &Sdef, p: Vec3, n: Vec3) -> (Vec3, Vec3) {
+    let m0 = matrix(matrices, sdef.bones[0]);
+    let m1 = matrix(matrices, sdef.bones[1]);
+    let w0 = sdef.weight;
+    let w1 = 1.0 - w0;
+    let rw = math::add(math::scale(sdef.r0, w0), math::scale(sdef.r1, w1));
+    let r0 = math::add(sdef.c, math::sub(sdef.r0, rw));
+    let r1 = math::add(sdef.c, math::sub(sdef.r1, rw));
+    let cr0 = math::scale(math::add(sdef.c, r0), 0.5);
+    let cr1 = math::scale(math::add(sdef.c, r1), 0.5);
+    let q = math::slerp(rotation(m1), rotation(m0), w0);
+    let position = math::add(
+        math::quat_rotate(q, math::sub(p, sdef.c)),
+        math::add(
+            math::scale(math::transform_point(m0, cr0), w0),
+            math::scale(math::transform_point(m1, cr1), w1),
+        ),
+    );
+    (position, math::quat_rotate(q, n))
+}
+
+fn qdef(matrices: &[Mat4], qdef: &Qdef, p: Vec3, n: Vec3) -> (Vec3, Vec3) {
+    let mut real = [0.0; 4];
+    let mut dual = [0.0; 4];
+    let mut pivot = None;
+    for (&bone, &weight) in qdef.bones.iter().zip(&qdef.weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        let m = matrix(matrices, bone);
+        let mut q = rotation(m);
+        let pivot = *pivot.get_or_insert(q);
+        if q.iter().zip(&pivot).map(|(a, b)| a * b).sum::<f32>() < 0.0 {
+            q = q.map(|v| -v);
+        }
+        let t = [m[3][0], m[3][1], m[3][2], 0.0];
+        let d = math::quat_mul(t, q).map(|v| v * 0.5);
+        (0..4).for_each(|i| {
+            real[i] += q[i] * weight;
+            dual[i] += d[i] * weight;
+        });
+    }
+    let len = real.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if len == 0.0 {
+        return (p, n);
+    }
+    let real = real.map(|v| v / len);
+    let dual = dual.map(|v| v / len);
+    let t = math::quat_mul(dual, math::quat_conjugate(real));
+    let position = math::add(
+        math::quat_rotate(real, p),
+        [t[0] * 2.0, t[1] * 2.0, t[2] * 2.0],
+    );
+    (position, math::quat_rotate(real, n))
+}
+
+pub fn skin_vertices(vertices: &[Vertex], matrices: &[[[f32; 4]; 4]]) -> SkinnedVertices {
+    let mut skinned = SkinnedVertices::default();
+    skin_vertices_into(vertices, matrices, &mut skinned);
+    skinned
+}
+
+pub fn skin_vertices_into(
+    vertices: &[Vertex],
+    matrices: &[[[f32; 4]; 4]],
+    skinned: &mut SkinnedVertices,
+) {
+    skinned.positions.clear();
+    skinned.normals.clear();
+    for v in vertices {
+        let (p, n) = (v.position, v.normal);
+        let (position, normal) = match &v.weight {
+            Weight::Bdef1(w) => linear(matrices, &[w.bone], &[1.0], p, n),
+            Weight::Bdef2(w) => linear(matrices, &w.bones, &[w.weight, 1.0 - w.weight], p, n),
+            Weight::Bdef4(w) => linear(matrices, &w.bones, &w.weights, p, n),
+            Weight::Sdef(w) => sdef(matrices, w, p, n),
+            Weight::Qdef(w) => qdef(matrices, w, p, n),
+        };
+        skinned.positions.push(position);
+        skinned.normals.push(math::normalize(normal));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn vertex(weight: Weight) -> Vertex {
+        Vertex {
+            position: [1.0, 1.0, 0.0],
+            normal: [0.0, 1.0, 0.0],
+            uv: [0.0; 2],
+            extended_uv: vec![],
+            weight,
+            edge_ratio: 1.0,
+        }
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5)
+    }
+
+    fn matrices() -> Vec<Mat4> {
+        let rotation = math::quat_from_axis_angle([0.0, 0.0, 1.0], FRAC_PI_2);
+        vec![
+            math::translation([0.0, 2.0, 0.0]),
+            math::from_rotation_translation(rotation, [0.0; 3]),
+        ]
+    }
+
+    #[test]
+    fn bdef() {
+        let vertices = [
+            vertex(Weight::Bdef1(Bdef1 { bone: Some(0) })),
+            vertex(Weight::Bdef1(Bdef1 { bone: Some(1) })),
+            vertex(Weight::Bdef2(Bdef2 {
+                bones: [Some(0), None],
+                weight: 0.5,
+            })),
+            vertex(Weight::Bdef4(Bdef4 {
+                bones: [Some(0), Some(1), None, None],
+                weights: [0.5, 0.5, 0.0, 0.0],
+            })),
+        ];
+        let skinned = skin_vertices(&vertices, &matrices());
+        assert!(close(skinned.positions[0], [1.0, 3.0, 0.0]));
+        assert!(close(skinned.positions[1], [-1.0, 1.0, 0.0]));
+        assert!(close(skinned.normals[1], [-1.0, 0.0, 0.0]));
+        assert!(close(skinned.positions[2], [1.0, 2.0, 0.0]));
+        assert!(close(skinned.positions[3], [0.0, 2.0, 0.0]));
+    }
+
+    #[test]
+    fn sdef() {
+        let matrices = matrices();
+        let rigid = vertex(Weight::Sdef(Sdef {
+            bones: [Some(1), Some(1)],
+            weight: 0.5,
+            c: [0.0; 3],
+            r0: [0.0; 3],
+            r1: [0.0; 3],
+        }));
+        let skinned = skin_vertices(&[rigid], &matrices);
+        assert!(close(skinned.positions[0], [-1.0, 1.0, 0.0]));
+        let blend = vertex(Weight::Sdef(Sdef {
+            bones: [Some(0), Some(1)],
+            weight: 0.5,
+            c: [0.0; 3],
+            r0: [0.0; 3],
+            r1: [0.0; 3],
+        }));
+        let skinned = skin_vertices(&[blend], &matrices);
+        let s = std::f32::consts::SQRT_2;
+        assert!(close(skinned.positions[0], [0.0, s + 1.0, 0.0]));
+        assert!(close(skinned.normals[0], [-0.5 * s, 0.5 * s, 0.0]));
+    }
+
+    #[test]
+    fn qdef() {
+        let vertices = [vertex(Weight::Qdef(Qdef {
+            bones: [Some(1), None, None, None],
+            weights: [1.0, 0.0, 0.0, 0.0],
+        }))];
+        let skinned = skin_vertices(&vertices, &matrices());
+        assert!(close(skinned.positions[0], [-1.0, 1.0, 0.0]));
+        let vertices = [vertex(Weight::Qdef(Qdef {
+            bones: [Some(0), None, None, None],
+            weights: [1.0, 0.0, 0.0, 0.0],
+        }))];
+        let skinned = skin_vertices(&vertices, &matrices());
+        assert!(close(skinned.positions[0], [1.0, 3.0, 0.0]));
+    }
+
+    #[test]
+    fn rest_pose() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let skeleton = Skeleton::from_model(&model).unwrap();
+        let mut evaluator = PoseEvaluator::new(skeleton);
+        (0..model.bones.len()).for_each(|i| evaluator.set_ik_enabled(i, false));
+        evaluator.evaluate(&Pose::default());
+        let skinned = skin_vertices(&model.vertices, &evaluator.skinning_matrices());
+        for (v, p) in model.vertices.iter().zip(&skinned.positions) {
+            assert!(close(v.position, *p));
+        }
+    }
+}
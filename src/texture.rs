@@ -0,0 +1,157 @@
+use super::*;
+use std::path::{Component, Path, PathBuf};
+
+pub fn shared_toon_name(index: u8) -> String {
+    format!("toon{:02}.bmp", index as u32 + 1)
+}
+
+// Texture names are written on Windows, so both separators are accepted.
+pub fn normalize_texture_path(path: &Path) -> PathBuf {
+    path.to_string_lossy()
+        .split(['\\', '/'])
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect()
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct ResolvedTextures {
+    pub textures: Vec<Option<PathBuf>>,
+    pub missing: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextureResolver {
+    base: PathBuf,
+    toon_dir: Option<PathBuf>,
+}
+
+impl TextureResolver {
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            toon_dir: None,
+        }
+    }
+
+    pub fn from_model_path(path: impl AsRef<Path>) -> Self {
+        Self::new(path.as_ref().parent().unwrap_or(Path::new("")))
+    }
+
+    // Directory holding the shared toon textures, e.g. MikuMikuDance's `Data`.
+    pub fn with_toon_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.toon_dir = Some(dir.into());
+        self
+    }
+
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    pub fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        find(&self.base, &normalize_texture_path(path))
+    }
+
+    pub fn resolve_all(&self, textures: &[PathBuf]) -> ResolvedTextures {
+        let mut resolved = ResolvedTextures::default();
+        for (i, path) in textures.iter().enumerate() {
+            let path = self.resolve(path);
+            if path.is_none() {
+                resolved.missing.push(i);
+            }
+            resolved.textures.push(path);
+        }
+        resolved
+    }
+
+    pub fn resolve_toon(&self, toon: &Toon, textures: &[PathBuf]) -> Option<PathBuf> {
+        match *toon {
+            Toon::Texture(index) => self.resolve(textures.get(index?)?),
+            // The model directory may override the shared toon textures.
+            Toon::Shared(n) => {
+                let name = PathBuf::from(shared_toon_name(n));
+                find(&self.base, &name).or_else(|| find(self.toon_dir.as_ref()?, &name))
+            }
+        }
+    }
+}
+
+fn find(base: &Path, relative: &Path) -> Option<PathBuf> {
+    let exact = base.join(relative);
+    if exact.is_file() {
+        return Some(exact);
+    }
+    let mut path = base.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => {
+                let candidate = path.join(name);
+                if candidate.exists() {
+                    path = candidate;
+                    continue;
+                }
+                let name = name.to_string_lossy().to_lowercase();
+                path = std::fs::read_dir(&path)
+                    .ok()?
+                    .filter_map(|e| e.ok())
+                    .find(|e| e.file_name().to_string_lossy().to_lowercase() == name)?
+                    .path();
+            }
+            Component::ParentDir => path.push(".."),
+            _ => path.push(component),
+        }
+    }
+    path.is_file().then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        let path = normalize_texture_path(Path::new("tex\\.\\sub//Face.PNG"));
+        assert!(path == Path::new("tex/sub/Face.PNG"));
+        assert!(shared_toon_name(0) == "toon01.bmp");
+        assert!(shared_toon_name(9) == "toon10.bmp");
+    }
+
+    #[test]
+    fn resolve() {
+        let resolver = TextureResolver::from_model_path("assets/Alicia/Alicia_solid.pmx");
+        assert!(resolver.base() == Path::new("assets/Alicia"));
+        let found = resolver
+            .resolve(Path::new("..\\ALICIA\\alicia_EYE.TGA"))
+            .unwrap();
+        assert!(found.ends_with("Alicia_eye.tga"));
+        let textures = vec![
+            PathBuf::from("Alicia_body.tga"),
+            PathBuf::from("FACE_S.bmp"),
+            PathBuf::from("missing.png"),
+        ];
+        let resolved = resolver.resolve_all(&textures);
+        assert!(resolved.missing == [2]);
+        assert!(resolved.textures[1]
+            .as_ref()
+            .unwrap()
+            .ends_with("face_s.bmp"));
+        assert!(resolver
+            .resolve_toon(&Toon::Texture(Some(1)), &textures)
+            .is_some());
+        assert!(resolver
+            .resolve_toon(&Toon::Texture(None), &textures)
+            .is_none());
+        assert!(resolver.resolve_toon(&Toon::Shared(0), &textures).is_none());
+        let resolver = resolver.with_toon_dir("assets/missing");
+        assert!(resolver.resolve_toon(&Toon::Shared(0), &textures).is_none());
+    }
+
+    #[test]
+    fn model() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let resolver = TextureResolver::from_model_path("assets/Alicia/Alicia_solid.pmx");
+        let resolved = resolver.resolve_all(&model.textures);
+        assert!(resolved.textures.len() == model.textures.len());
+        // The hair, wear, face and other textures are not shipped with the asset.
+        assert!(resolved.missing == [0, 4, 6, 11]);
+    }
+}
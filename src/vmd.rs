@@ -0,0 +1,704 @@
+use super::*;
+use crate::binary::{decode_sjis, encode_sjis, in_elements, truncate_sjis, BinaryCursor};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 30] = b"Vocaloid Motion Data 0002\0\0\0\0\0";
+const MAGIC_OLD: &[u8; 30] = b"Vocaloid Motion Data file\0\0\0\0\0";
+
+pub(crate) const BONE_NAME_LEN: usize = 15;
+pub(crate) const MORPH_NAME_LEN: usize = 15;
+pub(crate) const IK_NAME_LEN: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Bezier {
+    pub p1: [u8; 2],
+    pub p2: [u8; 2],
+}
+
+impl Default for Bezier {
+    fn default() -> Self {
+        Self {
+            p1: [20, 20],
+            p2: [107, 107],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct BoneInterpolation {
+    pub x: Bezier,
+    pub y: Bezier,
+    pub z: Bezier,
+    pub rotation: Bezier,
+}
+
+impl BoneInterpolation {
+    pub fn from_bytes(bytes: &[u8; 64]) -> Self {
+        let bezier = |c: usize| Bezier {
+            p1: [bytes[c], bytes[c + 4]],
+            p2: [bytes[c + 8], bytes[c + 12]],
+        };
+        Self {
+            x: bezier(0),
+            y: bezier(1),
+            z: bezier(2),
+            rotation: bezier(3),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 64] {
+        let curves = [self.x, self.y, self.z, self.rotation];
+        let mut row = [0u8; 16];
+        for (c, bezier) in curves.iter().enumerate() {
+            row[c] = bezier.p1[0];
+            row[c + 4] = bezier.p1[1];
+            row[c + 8] = bezier.p2[0];
+            row[c + 12] = bezier.p2[1];
+        }
+        // MikuMikuDance repeats the row three more times, each shifted by one
+        // byte and padded with 1, 0, 0.
+        let mut bytes = [0u8; 64];
+        for i in 0..4 {
+            for j in 0..16 {
+                bytes[i * 16 + j] = match (j + i).checked_sub(16) {
+                    None => row[j + i],
+                    Some(0) => 1,
+                    Some(_) => 0,
+                };
+            }
+        }
+        bytes
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct CameraInterpolation {
+    pub x: Bezier,
+    pub y: Bezier,
+    pub z: Bezier,
+    pub rotation: Bezier,
+    pub distance: Bezier,
+    pub view_angle: Bezier,
+}
+
+impl CameraInterpolation {
+    pub fn from_bytes(bytes: &[u8; 24]) -> Self {
+        let bezier = |c: usize| Bezier {
+            p1: [bytes[c * 4], bytes[c * 4 + 2]],
+            p2: [bytes[c * 4 + 1], bytes[c * 4 + 3]],
+        };
+        Self {
+            x: bezier(0),
+            y: bezier(1),
+            z: bezier(2),
+            rotation: bezier(3),
+            distance: bezier(4),
+            view_angle: bezier(5),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 24] {
+        let curves = [
+            self.x,
+            self.y,
+            self.z,
+            self.rotation,
+            self.distance,
+            self.view_angle,
+        ];
+        let mut bytes = [0u8; 24];
+        for (c, bezier) in curves.iter().enumerate() {
+            bytes[c * 4..c * 4 + 4].copy_from_slice(&[
+                bezier.p1[0],
+                bezier.p2[0],
+                bezier.p1[1],
+                bezier.p2[1],
+            ]);
+        }
+        bytes
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BoneKeyframe {
+    pub bone: String,
+    pub frame: u32,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub interpolation: BoneInterpolation,
+}
+
+#[derive(Clone, Debug)]
+pub struct MorphKeyframe {
+    pub morph: String,
+    pub frame: u32,
+    pub weight: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CameraKeyframe {
+    pub frame: u32,
+    pub distance: f32,
+    pub position: [f32; 3],
+    pub rotation: [f32; 3],
+    pub interpolation: CameraInterpolation,
+    pub view_angle: u32,
+    pub perspective: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct LightKeyframe {
+    pub frame: u32,
+    pub color: [f32; 3],
+    pub direction: [f32; 3],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShadowMode {
+    Off,
+    Mode1,
+    Mode2,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShadowKeyframe {
+    pub frame: u32,
+    pub mode: ShadowMode,
+    pub distance: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct IkState {
+    pub bone: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct IkKeyframe {
+    pub frame: u32,
+    pub visible: bool,
+    pub iks: Vec<IkState>,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Motion {
+    pub model_name: String,
+    pub bones: Vec<BoneKeyframe>,
+    pub morphs: Vec<MorphKeyframe>,
+    pub cameras: Vec<CameraKeyframe>,
+    pub lights: Vec<LightKeyframe>,
+    pub shadows: Vec<ShadowKeyframe>,
+    pub iks: Vec<IkKeyframe>,
+}
+
+fn read_bone_keyframe(data: &mut BinaryCursor) -> Result<BoneKeyframe, Error> {
+    Ok(BoneKeyframe {
+        bone: data.read_sjis::<BONE_NAME_LEN>()?,
+        frame: data.read_u32()?,
+        translation: data.read_vec::<3>()?,
+        rotation: data.read_vec::<4>()?,
+        interpolation: BoneInterpolation::from_bytes(&data.read_bin::<64>()?),
+    })
+}
+
+fn read_morph_keyframe(data: &mut BinaryCursor) -> Result<MorphKeyframe, Error> {
+    Ok(MorphKeyframe {
+        morph: data.read_sjis::<MORPH_NAME_LEN>()?,
+        frame: data.read_u32()?,
+        weight: data.read_f32()?,
+    })
+}
+
+fn read_camera_keyframe(data: &mut BinaryCursor) -> Result<CameraKeyframe, Error> {
+    Ok(CameraKeyframe {
+        frame: data.read_u32()?,
+        distance: data.read_f32()?,
+        position: data.read_vec::<3>()?,
+        rotation: data.read_vec::<3>()?,
+        interpolation: CameraInterpolation::from_bytes(&data.read_bin::<24>()?),
+        view_angle: data.read_u32()?,
+        perspective: data.read_u8()? == 0,
+    })
+}
+
+fn read_light_keyframe(data: &mut BinaryCursor) -> Result<LightKeyframe, Error> {
+    Ok(LightKeyframe {
+        frame: data.read_u32()?,
+        color: data.read_vec::<3>()?,
+        direction: data.read_vec::<3>()?,
+    })
+}
+
+fn read_shadow_keyframe(data: &mut BinaryCursor) -> Result<ShadowKeyframe, Error> {
+    let frame = data.read_u32()?;
+    let mode = match data.read_u8()? {
+        0 => ShadowMode::Off,
+        1 => ShadowMode::Mode1,
+        2 => ShadowMode::Mode2,
+        v => return Err(data.invalid("shadow mode", v)),
+    };
+    Ok(ShadowKeyframe {
+        frame,
+        mode,
+        distance: data.read_f32()?,
+    })
+}
+
+fn read_ik_keyframe(data: &mut BinaryCursor) -> Result<IkKeyframe, Error> {
+    let frame = data.read_u32()?;
+    let visible = data.read_u8()? != 0;
+    let len = data.read_u32()? as usize;
+    let len = data.read_len(len, IK_NAME_LEN + 1)?;
+    let iks = (0..len)
+        .map(|_| {
+            Ok(IkState {
+                bone: data.read_sjis::<IK_NAME_LEN>()?,
+                enabled: data.read_u8()? != 0,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(IkKeyframe {
+        frame,
+        visible,
+        iks,
+    })
+}
+
+fn read_section<T>(
+    data: &mut BinaryCursor,
+    section: Section,
+    element_size: usize,
+    f: impl Fn(&mut BinaryCursor) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    if data.is_end() {
+        return Ok(vec![]);
+    }
+    let len = data.read_u32().map_err(|e| e.in_element(section, None))? as usize;
+    let len = data
+        .read_len(len, element_size)
+        .map_err(|e| e.in_element(section, None))?;
+    in_elements(section, len, |_| f(data))
+}
+
+fn write_f32s(buf: &mut Vec<u8>, v: &[f32]) {
+    v.iter().for_each(|v| buf.extend(v.to_le_bytes()));
+}
+
+fn write_bone_keyframe(buf: &mut Vec<u8>, k: &BoneKeyframe) -> Result<(), Error> {
+    buf.extend(encode_sjis::<BONE_NAME_LEN>(&k.bone)?);
+    buf.extend(k.frame.to_le_bytes());
+    write_f32s(buf, &k.translation);
+    write_f32s(buf, &k.rotation);
+    buf.extend(k.interpolation.to_bytes());
+    Ok(())
+}
+
+fn write_morph_keyframe(buf: &mut Vec<u8>, k: &MorphKeyframe) -> Result<(), Error> {
+    buf.extend(encode_sjis::<MORPH_NAME_LEN>(&k.morph)?);
+    buf.extend(k.frame.to_le_bytes());
+    write_f32s(buf, &[k.weight]);
+    Ok(())
+}
+
+fn write_camera_keyframe(buf: &mut Vec<u8>, k: &CameraKeyframe) -> Result<(), Error> {
+    buf.extend(k.frame.to_le_bytes());
+    write_f32s(buf, &[k.distance]);
+    write_f32s(buf, &k.position);
+    write_f32s(buf, &k.rotation);
+    buf.extend(k.interpolation.to_bytes());
+    buf.extend(k.view_angle.to_le_bytes());
+    buf.push(if k.perspective { 0 } else { 1 });
+    Ok(())
+}
+
+fn write_light_keyframe(buf: &mut Vec<u8>, k: &LightKeyframe) -> Result<(), Error> {
+    buf.extend(k.frame.to_le_bytes());
+    write_f32s(buf, &k.color);
+    write_f32s(buf, &k.direction);
+    Ok(())
+}
+
+fn write_shadow_keyframe(buf: &mut Vec<u8>, k: &ShadowKeyframe) -> Result<(), Error> {
+    buf.extend(k.frame.to_le_bytes());
+    buf.push(match k.mode {
+        ShadowMode::Off => 0,
+        ShadowMode::Mode1 => 1,
+        ShadowMode::Mode2 => 2,
+    });
+    write_f32s(buf, &[k.distance]);
+    Ok(())
+}
+
+fn write_ik_keyframe(buf: &mut Vec<u8>, k: &IkKeyframe) -> Result<(), Error> {
+    buf.extend(k.frame.to_le_bytes());
+    buf.push(k.visible as u8);
+    buf.extend((k.iks.len() as u32).to_le_bytes());
+    for ik in &k.iks {
+        buf.extend(encode_sjis::<IK_NAME_LEN>(&ik.bone)?);
+        buf.push(ik.enabled as u8);
+    }
+    Ok(())
+}
+
+// Keyframes are written in ascending frame order. The sort is stable, so
+// keyframes of the same frame keep the order they had in the motion.
+fn write_section<T>(
+    buf: &mut Vec<u8>,
+    section: Section,
+    keyframes: &[T],
+    frame: impl Fn(&T) -> u32,
+    f: impl Fn(&mut Vec<u8>, &T) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let len = u32::try_from(keyframes.len())
+        .map_err(|_| Error::invalid_data("too many keyframes").in_element(section, None))?;
+    let mut order = (0..keyframes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| frame(&keyframes[i]));
+    buf.extend(len.to_le_bytes());
+    for i in order {
+        f(buf, &keyframes[i]).map_err(|e| e.in_element(section, Some(i)))?;
+    }
+    Ok(())
+}
+
+impl Motion {
+    pub fn read<T: Read>(mut reader: T) -> Result<Self, Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let mut data = BinaryCursor::new(data);
+        let magic = data.read_bin::<30>()?;
+        let model_name = if &magic == MAGIC {
+            data.read_sjis::<20>()?
+        } else if &magic == MAGIC_OLD {
+            data.read_sjis::<10>()?
+        } else {
+            return Err(Error::invalid_header("magic number"));
+        };
+        let data = &mut data;
+        Ok(Self {
+            model_name,
+            bones: read_section(data, Section::BoneKeyframes, 111, read_bone_keyframe)?,
+            morphs: read_section(data, Section::MorphKeyframes, 23, read_morph_keyframe)?,
+            cameras: read_section(data, Section::CameraKeyframes, 61, read_camera_keyframe)?,
+            lights: read_section(data, Section::LightKeyframes, 28, read_light_keyframe)?,
+            shadows: read_section(data, Section::ShadowKeyframes, 9, read_shadow_keyframe)?,
+            iks: read_section(data, Section::IkKeyframes, 9, read_ik_keyframe)?,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Self::read(BufReader::new(file))
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<W, Error> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend(encode_sjis::<20>(&self.model_name)?);
+        let buf = &mut buf;
+        write_section(
+            buf,
+            Section::BoneKeyframes,
+            &self.bones,
+            |k| k.frame,
+            write_bone_keyframe,
+        )?;
+        write_section(
+            buf,
+            Section::MorphKeyframes,
+            &self.morphs,
+            |k| k.frame,
+            write_morph_keyframe,
+        )?;
+        write_section(
+            buf,
+            Section::CameraKeyframes,
+            &self.cameras,
+            |k| k.frame,
+            write_camera_keyframe,
+        )?;
+        write_section(
+            buf,
+            Section::LightKeyframes,
+            &self.lights,
+            |k| k.frame,
+            write_light_keyframe,
+        )?;
+        write_section(
+            buf,
+            Section::ShadowKeyframes,
+            &self.shadows,
+            |k| k.frame,
+            write_shadow_keyframe,
+        )?;
+        write_section(
+            buf,
+            Section::IkKeyframes,
+            &self.iks,
+            |k| k.frame,
+            write_ik_keyframe,
+        )?;
+        writer.write_all(buf)?;
+        Ok(writer)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))?.flush()?;
+        Ok(())
+    }
+}
+
+fn insert_name(map: &mut HashMap<String, usize>, name: &str, index: usize, len: usize) {
+    let bytes = truncate_sjis(name, usize::MAX);
+    let keys = [
+        name.to_string(),
+        decode_sjis(&bytes[..bytes.len().min(len)]),
+        decode_sjis(&truncate_sjis(name, len)),
+    ];
+    for key in keys {
+        map.entry(key).or_insert(index);
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct NameIndex {
+    bones: HashMap<String, usize>,
+    morphs: HashMap<String, usize>,
+}
+
+impl NameIndex {
+    pub fn new<'a>(
+        bones: impl IntoIterator<Item = &'a str>,
+        morphs: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let mut this = Self::default();
+        for (i, name) in bones.into_iter().enumerate() {
+            insert_name(&mut this.bones, name, i, BONE_NAME_LEN);
+        }
+        for (i, name) in morphs.into_iter().enumerate() {
+            insert_name(&mut this.morphs, name, i, MORPH_NAME_LEN);
+        }
+        this
+    }
+
+    pub fn from_reader(reader: &Reader) -> Result<Self, Error> {
+        let bones = reader
+            .bones()
+            .map(|b| b.map(|b| b.name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let morphs = reader
+            .morphs()
+            .map(|m| m.map(|m| m.name))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(
+            bones.iter().map(|s| s.as_str()),
+            morphs.iter().map(|s| s.as_str()),
+        ))
+    }
+
+    pub fn from_model(model: &Model) -> Self {
+        Self::new(
+            model.bones.iter().map(|b| b.name.as_str()),
+            model.morphs.iter().map(|m| m.name.as_str()),
+        )
+    }
+
+    pub fn bone(&self, name: &str) -> Option<usize> {
+        self.bones.get(name).copied()
+    }
+
+    pub fn morph(&self, name: &str) -> Option<usize> {
+        self.morphs.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sjis<const N: usize>(s: &str) -> [u8; N] {
+        let bytes = truncate_sjis(s, N);
+        let mut buffer = [0u8; N];
+        buffer[..bytes.len()].copy_from_slice(&bytes);
+        buffer
+    }
+
+    fn f32s(data: &mut Vec<u8>, v: &[f32]) {
+        v.iter().for_each(|v| data.extend(v.to_le_bytes()));
+    }
+
+    fn new_data() -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend(sjis::<20>("アリシア・ソリッド"));
+        data.extend(2u32.to_le_bytes());
+        for (name, frame) in [("センター", 10u32), ("右ひじ", 0)] {
+            data.extend(sjis::<15>(name));
+            data.extend(frame.to_le_bytes());
+            f32s(&mut data, &[1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 1.0]);
+            data.extend(
+                BoneInterpolation {
+                    x: Bezier {
+                        p1: [10, 20],
+                        p2: [30, 40],
+                    },
+                    ..Default::default()
+                }
+                .to_bytes(),
+            );
+        }
+        data.extend(1u32.to_le_bytes());
+        data.extend(sjis::<15>("まばたき"));
+        data.extend(5u32.to_le_bytes());
+        f32s(&mut data, &[0.5]);
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        f32s(&mut data, &[-45.0, 0.0, 10.0, 0.0, 0.0, 0.0, 0.0]);
+        data.extend(CameraInterpolation::default().to_bytes());
+        data.extend(30u32.to_le_bytes());
+        data.push(0);
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        f32s(&mut data, &[0.6, 0.6, 0.6, -0.5, -1.0, 0.5]);
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.push(1);
+        f32s(&mut data, &[0.01]);
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.push(1);
+        data.extend(1u32.to_le_bytes());
+        data.extend(sjis::<20>("左足ＩＫ"));
+        data.push(0);
+        data
+    }
+
+    #[test]
+    fn read() {
+        let motion = Motion::from_bytes(&new_data()).unwrap();
+        assert!(motion.model_name == "アリシア・ソリッド");
+        assert!(motion.bones.len() == 2);
+        assert!(motion.bones[0].bone == "センター");
+        assert!(motion.bones[0].frame == 10);
+        assert!(motion.bones[0].interpolation.x.p2 == [30, 40]);
+        assert!(motion.bones[0].interpolation.rotation == Bezier::default());
+        assert!(motion.morphs[0].morph == "まばたき");
+        assert!(motion.morphs[0].weight == 0.5);
+        assert!(motion.cameras[0].view_angle == 30);
+        assert!(motion.cameras[0].perspective);
+        assert!(motion.lights[0].direction == [-0.5, -1.0, 0.5]);
+        assert!(motion.shadows[0].mode == ShadowMode::Mode1);
+        assert!(motion.iks[0].visible);
+        assert!(motion.iks[0].iks[0].bone == "左足ＩＫ");
+        assert!(!motion.iks[0].iks[0].enabled);
+    }
+
+    #[test]
+    fn optional_sections() {
+        let data = new_data();
+        let len = 30 + 20 + 4 + 111 * 2;
+        let motion = Motion::from_bytes(&data[..len]).unwrap();
+        assert!(motion.bones.len() == 2);
+        assert!(motion.morphs.is_empty() && motion.cameras.is_empty());
+        let e = Motion::from_bytes(&data[..len + 10]).unwrap_err();
+        assert!(e.info().unwrap().section == Some(Section::MorphKeyframes));
+    }
+
+    #[test]
+    fn bezier_bytes() {
+        let interpolation = BoneInterpolation {
+            x: Bezier {
+                p1: [1, 2],
+                p2: [3, 4],
+            },
+            y: Bezier {
+                p1: [5, 6],
+                p2: [7, 8],
+            },
+            z: Bezier::default(),
+            rotation: Bezier {
+                p1: [9, 10],
+                p2: [11, 12],
+            },
+        };
+        let bytes = interpolation.to_bytes();
+        assert!(bytes[..4] == [1, 5, 20, 9]);
+        assert!(bytes[15..18] == [12, 5, 20]);
+        assert!(bytes[61..] == [1, 0, 0]);
+        assert!(BoneInterpolation::from_bytes(&bytes) == interpolation);
+    }
+
+    #[test]
+    fn write() {
+        let data = new_data();
+        let motion = Motion::from_bytes(&data).unwrap();
+        let mut sorted = data.clone();
+        let bone = 30 + 20 + 4;
+        sorted[bone..bone + 111 * 2]
+            .copy_from_slice(&[&data[bone + 111..bone + 222], &data[bone..bone + 111]].concat());
+        assert!(motion.write(vec![]).unwrap() == sorted);
+    }
+
+    #[test]
+    fn write_names() {
+        let mut motion = Motion::default();
+        motion.morphs.push(MorphKeyframe {
+            morph: "とても長いモーフの名前".to_string(),
+            frame: 0,
+            weight: 1.0,
+        });
+        let data = motion.write(vec![]).unwrap();
+        let motion = Motion::from_bytes(&data).unwrap();
+        assert!(motion.morphs[0].morph == "とても長いモー");
+        let mut motion = Motion::default();
+        motion.bones.push(BoneKeyframe {
+            bone: "\u{1f600}".to_string(),
+            frame: 0,
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            interpolation: BoneInterpolation::default(),
+        });
+        let e = motion.write(vec![]).unwrap_err();
+        assert!(e.info().unwrap().section == Some(Section::BoneKeyframes));
+    }
+
+    #[test]
+    fn names() {
+        let reader =
+            Reader::from_bytes(include_bytes!("../assets/Alicia/Alicia_solid.pmx")).unwrap();
+        let names = NameIndex::from_reader(&reader).unwrap();
+        let bone = reader.bone(10).unwrap();
+        assert!(names.bone(&bone.name) == Some(10));
+        let long = reader
+            .bones()
+            .map(|b| b.unwrap())
+            .position(|b| truncate_sjis(&b.name, usize::MAX).len() > BONE_NAME_LEN);
+        if let Some(i) = long {
+            let name = decode_sjis(&sjis::<BONE_NAME_LEN>(&reader.bone(i).unwrap().name));
+            assert!(names.bone(&name).is_some());
+        }
+        assert!(names.morph(&reader.morph(0).unwrap().name) == Some(0));
+        assert!(names.bone("存在しない").is_none());
+    }
+
+    #[test]
+    fn truncated_names() {
+        let name = "右腕捩りボーン先端";
+        let names = NameIndex::new(["センター", name], []);
+        let bytes = truncate_sjis(name, usize::MAX);
+        assert!(bytes.len() > BONE_NAME_LEN);
+        // Cut in the middle of the eighth character.
+        let raw = decode_sjis(&bytes[..BONE_NAME_LEN]);
+        let whole = decode_sjis(&sjis::<BONE_NAME_LEN>(name));
+        assert!(whole == "右腕捩りボーン");
+        assert!(raw != whole);
+        assert!(names.bone(name) == Some(1));
+        assert!(names.bone(&raw) == Some(1));
+        assert!(names.bone(&whole) == Some(1));
+    }
+}
@@ -0,0 +1,316 @@
+use super::*;
+use crate::binary::decode_sjis;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &str = "Vocaloid Pose Data file";
+
+#[derive(Clone, Debug)]
+pub struct VpdBone {
+    pub name: String,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+#[derive(Clone, Debug)]
+pub struct VpdMorph {
+    pub name: String,
+    pub weight: f32,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Vpd {
+    pub model_name: String,
+    pub bones: Vec<VpdBone>,
+    pub morphs: Vec<VpdMorph>,
+}
+
+struct Lines<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    line: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn invalid(&self, msg: &str) -> Error {
+        Error::invalid_data(format!("{msg} at line {}", self.line + 1))
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        for (i, line) in self.lines.by_ref() {
+            self.line = i;
+            let line = line.split_once("//").map_or(line, |(line, _)| line).trim();
+            if !line.is_empty() {
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    fn expect(&mut self, msg: &str) -> Result<&'a str, Error> {
+        self.next().ok_or_else(|| self.invalid(msg))
+    }
+
+    fn floats<const N: usize>(&mut self, msg: &str) -> Result<[f32; N], Error> {
+        let line = self.expect(msg)?;
+        let line = line.strip_suffix(';').ok_or_else(|| self.invalid(msg))?;
+        let mut values = [0.0; N];
+        let mut items = line.split(',');
+        for v in &mut values {
+            *v = items
+                .next()
+                .and_then(|s| s.trim().parse().ok())
+                .ok_or_else(|| self.invalid(msg))?;
+        }
+        if items.next().is_some() {
+            return Err(self.invalid(msg));
+        }
+        Ok(values)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        match self.expect("}")? {
+            "}" => Ok(()),
+            _ => Err(self.invalid("}")),
+        }
+    }
+}
+
+impl Vpd {
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let mut lines = Lines {
+            lines: s.lines().enumerate(),
+            line: 0,
+        };
+        if lines.next().map(|l| l.trim_start_matches('\u{feff}')) != Some(MAGIC) {
+            return Err(Error::invalid_header("magic number"));
+        }
+        let model_name = lines.expect("model name")?;
+        let model_name = model_name
+            .strip_suffix(';')
+            .ok_or_else(|| lines.invalid("model name"))?;
+        let model_name = model_name
+            .strip_suffix(".osm")
+            .unwrap_or(model_name)
+            .to_string();
+        let bones_len = lines.expect("bone count")?;
+        let bones_len = bones_len
+            .strip_suffix(';')
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .ok_or_else(|| lines.invalid("bone count"))?;
+        let mut this = Self {
+            model_name,
+            bones: vec![],
+            morphs: vec![],
+        };
+        while let Some(line) = lines.next() {
+            let (kind, name) = line.split_once('{').ok_or_else(|| lines.invalid("{"))?;
+            let name = name.trim().to_string();
+            if kind.starts_with("Bone") {
+                let translation = lines.floats::<3>("bone translation")?;
+                let rotation = lines.floats::<4>("bone rotation")?;
+                this.bones.push(VpdBone {
+                    name,
+                    translation,
+                    rotation,
+                });
+            } else if kind.starts_with("Morph") {
+                let [weight] = lines.floats::<1>("morph weight")?;
+                this.morphs.push(VpdMorph { name, weight });
+            } else {
+                return Err(lines.invalid("unknown block"));
+            }
+            lines.close()?;
+        }
+        if this.bones.len() != bones_len {
+            return Err(Error::invalid_data(format!(
+                "{} bones do not match the bone count {bones_len}",
+                this.bones.len()
+            )));
+        }
+        Ok(this)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        Self::parse(&decode_sjis(data))
+    }
+
+    pub fn read<T: Read>(mut reader: T) -> Result<Self, Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::read(File::open(path)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+        write!(s, "{MAGIC}\r\n\r\n").unwrap();
+        write!(s, "{}.osm;\r\n", self.model_name).unwrap();
+        write!(s, "{};\r\n\r\n", self.bones.len()).unwrap();
+        for (i, bone) in self.bones.iter().enumerate() {
+            let [x, y, z] = bone.translation;
+            let [qx, qy, qz, qw] = bone.rotation;
+            write!(s, "Bone{i}{{{}\r\n", bone.name).unwrap();
+            write!(s, "  {x:.6},{y:.6},{z:.6};\r\n").unwrap();
+            write!(s, "  {qx:.6},{qy:.6},{qz:.6},{qw:.6};\r\n").unwrap();
+            write!(s, "}}\r\n\r\n").unwrap();
+        }
+        for (i, morph) in self.morphs.iter().enumerate() {
+            write!(s, "Morph{i}{{{}\r\n", morph.name).unwrap();
+            write!(s, "  {:.6};\r\n", morph.weight).unwrap();
+            write!(s, "}}\r\n\r\n").unwrap();
+        }
+        s
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<W, Error> {
+        let text = self.to_text();
+        let (data, _, unmappable) = encoding_rs::SHIFT_JIS.encode(&text);
+        if unmappable {
+            return Err(Error::invalid_data("not representable in Shift-JIS"));
+        }
+        writer.write_all(&data)?;
+        Ok(writer)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))?.flush()?;
+        Ok(())
+    }
+
+    pub fn apply<'a>(&'a self, names: &NameIndex, pose: &mut Pose) -> Vec<&'a str> {
+        let mut unresolved = vec![];
+        for bone in &self.bones {
+            match names.bone(&bone.name).and_then(|i| pose.bones.get_mut(i)) {
+                Some(transform) => {
+                    *transform = BoneTransform {
+                        translation: bone.translation,
+                        rotation: bone.rotation,
+                    };
+                }
+                None => unresolved.push(bone.name.as_str()),
+            }
+        }
+        for morph in &self.morphs {
+            match names
+                .morph(&morph.name)
+                .and_then(|i| pose.morphs.get_mut(i))
+            {
+                Some(weight) => *weight = morph.weight,
+                None => unresolved.push(morph.name.as_str()),
+            }
+        }
+        unresolved
+    }
+
+    pub fn from_pose<'a>(
+        model_name: &str,
+        pose: &Pose,
+        bones: impl IntoIterator<Item = &'a str>,
+        morphs: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        Self {
+            model_name: model_name.to_string(),
+            bones: bones
+                .into_iter()
+                .zip(&pose.bones)
+                .filter(|(_, t)| !t.is_identity())
+                .map(|(name, t)| VpdBone {
+                    name: name.to_string(),
+                    translation: t.translation,
+                    rotation: t.rotation,
+                })
+                .collect(),
+            morphs: morphs
+                .into_iter()
+                .zip(&pose.morphs)
+                .filter(|(_, &w)| w != 0.0)
+                .map(|(name, &weight)| VpdMorph {
+                    name: name.to_string(),
+                    weight,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn from_model_pose(model: &Model, pose: &Pose) -> Self {
+        Self::from_pose(
+            &model.name,
+            pose,
+            model.bones.iter().map(|b| b.name.as_str()),
+            model.morphs.iter().map(|m| m.name.as_str()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Vocaloid Pose Data file\r
+\r
+アリシア・ソリッド.osm;\t\t// 親ファイル名\r
+2;\t\t\t\t// 総ポーズボーン数\r
+\r
+Bone0{センター\r
+  0.000000,1.500000,0.000000;\t\t\t\t// trans x,y,z\r
+  0.000000,0.000000,0.000000,1.000000;\t\t// Quaternion x,y,z,w\r
+}\r
+\r
+Bone1{存在しない\r
+  0.000000,0.000000,0.000000;\r
+  0.000000,0.600000,0.000000,0.800000;\r
+}\r
+\r
+Morph0{まばたき\r
+  0.500000;\r
+}\r
+";
+
+    #[test]
+    fn parse() {
+        let (data, _, _) = encoding_rs::SHIFT_JIS.encode(TEXT);
+        let vpd = Vpd::from_bytes(&data).unwrap();
+        assert!(vpd.model_name == "アリシア・ソリッド");
+        assert!(vpd.bones.len() == 2);
+        assert!(vpd.bones[0].name == "センター");
+        assert!(vpd.bones[0].translation == [0.0, 1.5, 0.0]);
+        assert!(vpd.bones[1].rotation == [0.0, 0.6, 0.0, 0.8]);
+        assert!(vpd.morphs[0].weight == 0.5);
+        assert!(Vpd::parse(&TEXT.replace("2;", "3;")).is_err());
+        assert!(Vpd::parse(&TEXT.replace("0.500000;", "0.5")).is_err());
+    }
+
+    #[test]
+    fn write() {
+        let vpd = Vpd::parse(TEXT).unwrap();
+        let data = vpd.write(vec![]).unwrap();
+        let vpd2 = Vpd::from_bytes(&data).unwrap();
+        assert!(vpd2.bones.len() == 2);
+        assert!(vpd2.bones[1].rotation == vpd.bones[1].rotation);
+        assert!(vpd2.morphs[0].name == "まばたき");
+    }
+
+    #[test]
+    fn apply() {
+        let model = Model::load("assets/Alicia/Alicia_solid.pmx").unwrap();
+        let names = NameIndex::from_model(&model);
+        let mut vpd = Vpd::parse(TEXT).unwrap();
+        vpd.bones[0].name = model.bones[1].name.clone();
+        vpd.morphs[0].name = model.morphs[2].name.clone();
+        let mut pose = Pose::new(model.bones.len(), model.morphs.len());
+        let unresolved = vpd.apply(&names, &mut pose);
+        assert!(unresolved == ["存在しない"]);
+        assert!(pose.bones[1].translation == [0.0, 1.5, 0.0]);
+        assert!(pose.morphs[2] == 0.5);
+        let vpd = Vpd::from_model_pose(&model, &pose);
+        assert!(vpd.bones.len() == 1);
+        assert!(vpd.bones[0].name == model.bones[1].name);
+        assert!(vpd.morphs.len() == 1);
+    }
+}
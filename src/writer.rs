@@ -0,0 +1,822 @@
+use super::*;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stage {
+    Info,
+    Vertices,
+    Faces,
+    Textures,
+    Materials,
+    Bones,
+    Morphs,
+    DisplayGroups,
+    Rigids,
+    Joints,
+    SoftBodies,
+    End,
+}
+
+struct DataWriter<'a, W: Write> {
+    writer: &'a mut W,
+    header: &'a Header,
+}
+
+impl<'a, W: Write> DataWriter<'a, W> {
+    fn new(writer: &'a mut W, header: &'a Header) -> Self {
+        Self { writer, header }
+    }
+
+    fn write_bin(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    fn write_u8(&mut self, v: u8) -> Result<(), Error> {
+        self.write_bin(&[v])
+    }
+
+    fn write_u16(&mut self, v: u16) -> Result<(), Error> {
+        self.write_bin(&v.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, v: u32) -> Result<(), Error> {
+        self.write_bin(&v.to_le_bytes())
+    }
+
+    fn write_i32(&mut self, v: i32) -> Result<(), Error> {
+        self.write_bin(&v.to_le_bytes())
+    }
+
+    fn write_f32(&mut self, v: f32) -> Result<(), Error> {
+        self.write_bin(&v.to_le_bytes())
+    }
+
+    fn write_vec<const N: usize>(&mut self, v: &[f32; N]) -> Result<(), Error> {
+        for x in v {
+            self.write_f32(*x)?;
+        }
+        Ok(())
+    }
+
+    fn write_len(&mut self, len: usize) -> Result<(), Error> {
+        let len = u32::try_from(len).map_err(|_| Error::invalid_data("length"))?;
+        self.write_u32(len)
+    }
+
+    fn write_string(&mut self, s: &str) -> Result<(), Error> {
+        match self.header.encoding {
+            Encoding::Utf16 => {
+                let buffer = s
+                    .encode_utf16()
+                    .flat_map(|c| c.to_le_bytes())
+                    .collect::<Vec<_>>();
+                self.write_len(buffer.len())?;
+                self.write_bin(&buffer)
+            }
+            Encoding::Utf8 => {
+                self.write_len(s.len())?;
+                self.write_bin(s.as_bytes())
+            }
+        }
+    }
+
+    fn write_signed_index(&mut self, size: u64, index: Option<usize>) -> Result<(), Error> {
+        let v = match index {
+            Some(v) => i64::try_from(v).map_err(|_| Error::invalid_data("index"))?,
+            None => -1,
+        };
+        match size {
+            1 => {
+                let v = i8::try_from(v).map_err(|_| Error::invalid_data("index size"))?;
+                self.write_bin(&v.to_le_bytes())
+            }
+            2 => {
+                let v = i16::try_from(v).map_err(|_| Error::invalid_data("index size"))?;
+                self.write_bin(&v.to_le_bytes())
+            }
+            4 => {
+                let v = i32::try_from(v).map_err(|_| Error::invalid_data("index size"))?;
+                self.write_bin(&v.to_le_bytes())
+            }
+            _ => Err(Error::invalid_header("index size")),
+        }
+    }
+
+    fn write_vertex_index(&mut self, index: usize) -> Result<(), Error> {
+        match self.header.vertex_index_size {
+            1 => {
+                let v = u8::try_from(index).map_err(|_| Error::invalid_data("index size"))?;
+                self.write_u8(v)
+            }
+            2 => {
+                let v = u16::try_from(index).map_err(|_| Error::invalid_data("index size"))?;
+                self.write_u16(v)
+            }
+            4 => {
+                let v = i32::try_from(index).map_err(|_| Error::invalid_data("index size"))?;
+                self.write_i32(v)
+            }
+            _ => Err(Error::invalid_header("index size")),
+        }
+    }
+
+    fn write_texture_index(&mut self, index: Option<usize>) -> Result<(), Error> {
+        self.write_signed_index(self.header.texture_index_size, index)
+    }
+
+    fn write_material_index(&mut self, index: Option<usize>) -> Result<(), Error> {
+        self.write_signed_index(self.header.material_index_size, index)
+    }
+
+    fn write_bone_index(&mut self, index: Option<usize>) -> Result<(), Error> {
+        self.write_signed_index(self.header.bone_index_size, index)
+    }
+
+    fn write_morph_index(&mut self, index: Option<usize>) -> Result<(), Error> {
+        self.write_signed_index(self.header.morph_index_size, index)
+    }
+
+    fn write_rigid_index(&mut self, index: Option<usize>) -> Result<(), Error> {
+        self.write_signed_index(self.header.rigid_index_size, index)
+    }
+}
+
+pub struct Writer<W: Write> {
+    writer: W,
+    header: Header,
+    stage: Stage,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut writer: W, header: Header) -> Result<Self, Error> {
+        if header.version != 2.0 && header.version != 2.1 {
+            return Err(Error::UnsupportedVersion);
+        }
+        if header.extended_uv > 4 {
+            return Err(Error::invalid_header("extended uv"));
+        }
+        let index_sizes = [
+            header.vertex_index_size,
+            header.texture_index_size,
+            header.material_index_size,
+            header.bone_index_size,
+            header.morph_index_size,
+            header.rigid_index_size,
+        ];
+        for index_size in index_sizes {
+            match index_size {
+                1 | 2 | 4 => {}
+                _ => return Err(Error::invalid_header("index size")),
+            }
+        }
+        writer.write_all(b"PMX ")?;
+        writer.write_all(&header.version.to_le_bytes())?;
+        writer.write_all(&[8])?;
+        writer.write_all(&[header.encoding as u8, header.extended_uv])?;
+        writer.write_all(&index_sizes.map(|size| size as u8))?;
+        Ok(Self {
+            writer,
+            header,
+            stage: Stage::Info,
+        })
+    }
+
+    #[inline]
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn begin(&mut self, stage: Stage) -> Result<DataWriter<'_, W>, Error> {
+        if self.stage != stage {
+            return Err(Error::invalid_data("section order"));
+        }
+        Ok(DataWriter::new(&mut self.writer, &self.header))
+    }
+
+    fn end(&mut self, stage: Stage) {
+        self.stage = match stage {
+            Stage::Info => Stage::Vertices,
+            Stage::Vertices => Stage::Faces,
+            Stage::Faces => Stage::Textures,
+            Stage::Textures => Stage::Materials,
+            Stage::Materials => Stage::Bones,
+            Stage::Bones => Stage::Morphs,
+            Stage::Morphs => Stage::DisplayGroups,
+            Stage::DisplayGroups => Stage::Rigids,
+            Stage::Rigids => Stage::Joints,
+            Stage::Joints if self.header.is_v2_1() => Stage::SoftBodies,
+            Stage::Joints | Stage::SoftBodies | Stage::End => Stage::End,
+        };
+    }
+
+    pub fn write_info(
+        &mut self,
+        name: &str,
+        name_en: &str,
+        comment: &str,
+        comment_en: &str,
+    ) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Info)?;
+        data.write_string(name)?;
+        data.write_string(name_en)?;
+        data.write_string(comment)?;
+        data.write_string(comment_en)?;
+        self.end(Stage::Info);
+        Ok(())
+    }
+
+    pub fn write_vertices(&mut self, vertices: &[Vertex]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Vertices)?;
+        data.write_len(vertices.len())?;
+        for vertex in vertices {
+            if vertex.extended_uv.len() != data.header.extended_uv as usize {
+                return Err(Error::invalid_data("vertex extended uv"));
+            }
+            data.write_vec(&vertex.position)?;
+            data.write_vec(&vertex.normal)?;
+            data.write_vec(&vertex.uv)?;
+            for uv in &vertex.extended_uv {
+                data.write_vec(uv)?;
+            }
+            match &vertex.weight {
+                Weight::Bdef1(w) => {
+                    data.write_u8(0)?;
+                    data.write_bone_index(w.bone)?;
+                }
+                Weight::Bdef2(w) => {
+                    data.write_u8(1)?;
+                    for bone in w.bones {
+                        data.write_bone_index(bone)?;
+                    }
+                    data.write_f32(w.weight)?;
+                }
+                Weight::Bdef4(w) => {
+                    data.write_u8(2)?;
+                    for bone in w.bones {
+                        data.write_bone_index(bone)?;
+                    }
+                    data.write_vec(&w.weights)?;
+                }
+                Weight::Sdef(w) => {
+                    data.write_u8(3)?;
+                    for bone in w.bones {
+                        data.write_bone_index(bone)?;
+                    }
+                    data.write_f32(w.weight)?;
+                    data.write_vec(&w.c)?;
+                    data.write_vec(&w.r0)?;
+                    data.write_vec(&w.r1)?;
+                }
+                Weight::Qdef(w) => {
+                    if !data.header.is_v2_1() {
+                        return Err(Error::invalid_data("vertex weight type"));
+                    }
+                    data.write_u8(4)?;
+                    for bone in w.bones {
+                        data.write_bone_index(bone)?;
+                    }
+                    data.write_vec(&w.weights)?;
+                }
+            }
+            data.write_f32(vertex.edge_ratio)?;
+        }
+        self.end(Stage::Vertices);
+        Ok(())
+    }
+
+    pub fn write_faces(&mut self, faces: &[usize]) -> Result<(), Error> {
+        if !faces.len().is_multiple_of(3) {
+            return Err(Error::invalid_data("faces"));
+        }
+        let mut data = self.begin(Stage::Faces)?;
+        data.write_len(faces.len())?;
+        for &index in faces {
+            data.write_vertex_index(index)?;
+        }
+        self.end(Stage::Faces);
+        Ok(())
+    }
+
+    pub fn write_textures<P: AsRef<Path>>(&mut self, textures: &[P]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Textures)?;
+        data.write_len(textures.len())?;
+        for texture in textures {
+            let path = texture
+                .as_ref()
+                .to_str()
+                .ok_or_else(|| Error::invalid_data("texture path"))?;
+            data.write_string(path)?;
+        }
+        self.end(Stage::Textures);
+        Ok(())
+    }
+
+    pub fn write_materials(&mut self, materials: &[Material]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Materials)?;
+        data.write_len(materials.len())?;
+        for material in materials {
+            data.write_string(&material.name)?;
+            data.write_string(&material.name_en)?;
+            data.write_vec(&material.diffuse)?;
+            data.write_vec(&material.specular)?;
+            data.write_f32(material.specular_power)?;
+            data.write_vec(&material.ambient)?;
+            let mut flags = 0;
+            if material.both {
+                flags |= 0x01;
+            }
+            if material.ground_shadow {
+                flags |= 0x02;
+            }
+            if material.self_shadow_map {
+                flags |= 0x04;
+            }
+            if material.self_shadow {
+                flags |= 0x08;
+            }
+            if material.edge {
+                flags |= 0x10;
+            }
+            data.write_u8(flags)?;
+            data.write_vec(&material.edge_color)?;
+            data.write_f32(material.edge_size)?;
+            data.write_texture_index(material.texture)?;
+            data.write_texture_index(material.sphere)?;
+            data.write_u8(match material.sphere_mode {
+                SphereMode::None => 0,
+                SphereMode::Add => 1,
+                SphereMode::Mul => 2,
+                SphereMode::SubTexture => 3,
+            })?;
+            match material.toon {
+                Toon::Texture(index) => {
+                    data.write_u8(0)?;
+                    data.write_texture_index(index)?;
+                }
+                Toon::Shared(v) => {
+                    data.write_u8(1)?;
+                    data.write_u8(v)?;
+                }
+            }
+            data.write_string(&material.memo)?;
+            data.write_u32(material.index_count)?;
+        }
+        self.end(Stage::Materials);
+        Ok(())
+    }
+
+    pub fn write_bones(&mut self, bones: &[Bone]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Bones)?;
+        data.write_len(bones.len())?;
+        for bone in bones {
+            data.write_string(&bone.name)?;
+            data.write_string(&bone.name_en)?;
+            data.write_vec(&bone.position)?;
+            data.write_bone_index(bone.parent)?;
+            data.write_i32(bone.deform_hierarchy)?;
+            let addition = bone
+                .addition
+                .as_ref()
+                .filter(|addition| addition.rotation || addition.translation);
+            let mut flags = 0u16;
+            if let ConnectTo::Bone(_) = bone.connected_to {
+                flags |= 0x0001;
+            }
+            if bone.rotatable {
+                flags |= 0x0002;
+            }
+            if bone.translatable {
+                flags |= 0x0004;
+            }
+            if bone.visibility {
+                flags |= 0x0008;
+            }
+            if bone.operable {
+                flags |= 0x0010;
+            }
+            if bone.ik.is_some() {
+                flags |= 0x0020;
+            }
+            if let Some(addition) = addition {
+                if addition.local {
+                    flags |= 0x0080;
+                }
+                if addition.rotation {
+                    flags |= 0x0100;
+                }
+                if addition.translation {
+                    flags |= 0x0200;
+                }
+            }
+            if bone.fixed_pole.is_some() {
+                flags |= 0x0400;
+            }
+            if bone.local_pole.is_some() {
+                flags |= 0x0800;
+            }
+            if bone.after_physics {
+                flags |= 0x1000;
+            }
+            if bone.external_parent.is_some() {
+                flags |= 0x2000;
+            }
+            data.write_u16(flags)?;
+            match bone.connected_to {
+                ConnectTo::Offset(offset) => data.write_vec(&offset)?,
+                ConnectTo::Bone(index) => data.write_bone_index(index)?,
+            }
+            if let Some(addition) = addition {
+                data.write_bone_index(addition.bone)?;
+                data.write_f32(addition.ratio)?;
+            }
+            if let Some(fixed_pole) = &bone.fixed_pole {
+                data.write_vec(fixed_pole)?;
+            }
+            if let Some(local_pole) = &bone.local_pole {
+                data.write_vec(&local_pole.x)?;
+                data.write_vec(&local_pole.z)?;
+            }
+            if let Some(external_parent) = bone.external_parent {
+                let v = i32::try_from(external_parent)
+                    .map_err(|_| Error::invalid_data("bone external parent"))?;
+                data.write_i32(v)?;
+            }
+            if let Some(ik) = &bone.ik {
+                data.write_bone_index(ik.target_bone)?;
+                data.write_u32(ik.loop_count)?;
+                data.write_f32(ik.angle)?;
+                data.write_len(ik.links.len())?;
+                for link in &ik.links {
+                    data.write_bone_index(link.bone)?;
+                    match &link.limit {
+                        Some(limit) => {
+                            data.write_u8(1)?;
+                            data.write_vec(&limit.lower)?;
+                            data.write_vec(&limit.upper)?;
+                        }
+                        None => data.write_u8(0)?,
+                    }
+                }
+            }
+        }
+        self.end(Stage::Bones);
+        Ok(())
+    }
+
+    pub fn write_morphs(&mut self, morphs: &[Morph]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Morphs)?;
+        data.write_len(morphs.len())?;
+        for morph in morphs {
+            data.write_string(&morph.name)?;
+            data.write_string(&morph.name_en)?;
+            data.write_u8(match morph.panel {
+                Panel::Reserved => 0,
+                Panel::Eyebrow => 1,
+                Panel::Eye => 2,
+                Panel::Mouth => 3,
+                Panel::Other => 4,
+            })?;
+            match &morph.kind {
+                morph::Kind::Group(v) => {
+                    data.write_u8(0)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_morph_index(e.morph)?;
+                        data.write_f32(e.ratio)?;
+                    }
+                }
+                morph::Kind::Vertex(v) => {
+                    data.write_u8(1)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_vertex_index(e.vertex)?;
+                        data.write_vec(&e.offset)?;
+                    }
+                }
+                morph::Kind::Bone(v) => {
+                    data.write_u8(2)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_bone_index(e.bone)?;
+                        data.write_vec(&e.offset)?;
+                        data.write_vec(&e.rotation)?;
+                    }
+                }
+                morph::Kind::Uv(v) => {
+                    data.write_u8(3)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_vertex_index(e.vertex)?;
+                        data.write_vec(&e.offset)?;
+                    }
+                }
+                morph::Kind::ExtendedUv(n, v) => {
+                    if *n >= 4 {
+                        return Err(Error::invalid_data("morph extended uv"));
+                    }
+                    data.write_u8(4 + *n as u8)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_vertex_index(e.vertex)?;
+                        data.write_vec(&e.offset)?;
+                    }
+                }
+                morph::Kind::Material(v) => {
+                    data.write_u8(8)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_material_index(e.material)?;
+                        data.write_u8(match e.op {
+                            morph::MaterialOp::Mul => 0,
+                            morph::MaterialOp::Add => 1,
+                        })?;
+                        data.write_vec(&e.diffuse)?;
+                        data.write_vec(&e.specular)?;
+                        data.write_f32(e.specular_power)?;
+                        data.write_vec(&e.ambient)?;
+                        data.write_vec(&e.edge_color)?;
+                        data.write_f32(e.edge_size)?;
+                        data.write_vec(&e.texture)?;
+                        data.write_vec(&e.sphere)?;
+                        data.write_vec(&e.toon)?;
+                    }
+                }
+                morph::Kind::Flip(v) => {
+                    if !data.header.is_v2_1() {
+                        return Err(Error::invalid_data("morph type"));
+                    }
+                    data.write_u8(9)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_morph_index(e.morph)?;
+                        data.write_f32(e.ratio)?;
+                    }
+                }
+                morph::Kind::Impulse(v) => {
+                    if !data.header.is_v2_1() {
+                        return Err(Error::invalid_data("morph type"));
+                    }
+                    data.write_u8(10)?;
+                    data.write_len(v.len())?;
+                    for e in v {
+                        data.write_rigid_index(e.rigid)?;
+                        data.write_u8(e.local as u8)?;
+                        data.write_vec(&e.velocity)?;
+                        data.write_vec(&e.torque)?;
+                    }
+                }
+            }
+        }
+        self.end(Stage::Morphs);
+        Ok(())
+    }
+
+    pub fn write_display_groups(&mut self, display_groups: &[DisplayGroup]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::DisplayGroups)?;
+        data.write_len(display_groups.len())?;
+        for display_group in display_groups {
+            data.write_string(&display_group.name)?;
+            data.write_string(&display_group.name_en)?;
+            data.write_u8(display_group.special as u8)?;
+            data.write_len(display_group.elements.len())?;
+            for element in &display_group.elements {
+                match element {
+                    DisplayElement::Bone(index) => {
+                        data.write_u8(0)?;
+                        data.write_bone_index(*index)?;
+                    }
+                    DisplayElement::Morph(index) => {
+                        data.write_u8(1)?;
+                        data.write_morph_index(*index)?;
+                    }
+                }
+            }
+        }
+        self.end(Stage::DisplayGroups);
+        Ok(())
+    }
+
+    pub fn write_rigids(&mut self, rigids: &[Rigid]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Rigids)?;
+        data.write_len(rigids.len())?;
+        for rigid in rigids {
+            data.write_string(&rigid.name)?;
+            data.write_string(&rigid.name_en)?;
+            data.write_bone_index(rigid.bone)?;
+            data.write_u8(rigid.group)?;
+            data.write_u16(rigid.non_collision_groups)?;
+            data.write_u8(match rigid.shape {
+                rigid::Shape::Sphere => 0,
+                rigid::Shape::Box => 1,
+                rigid::Shape::Capsule => 2,
+            })?;
+            data.write_vec(&rigid.size)?;
+            data.write_vec(&rigid.position)?;
+            data.write_vec(&rigid.rotation)?;
+            data.write_f32(rigid.mass)?;
+            data.write_f32(rigid.dump_translation)?;
+            data.write_f32(rigid.dump_rotation)?;
+            data.write_f32(rigid.repulsive)?;
+            data.write_f32(rigid.friction)?;
+            data.write_u8(match rigid.method {
+                rigid::Method::Static => 0,
+                rigid::Method::Dynamic => 1,
+                rigid::Method::DynamicWithBone => 2,
+            })?;
+        }
+        self.end(Stage::Rigids);
+        Ok(())
+    }
+
+    pub fn write_joints(&mut self, joints: &[Joint]) -> Result<(), Error> {
+        let mut data = self.begin(Stage::Joints)?;
+        data.write_len(joints.len())?;
+        for joint in joints {
+            data.write_string(&joint.name)?;
+            data.write_string(&joint.name_en)?;
+            if joint.kind != joint::Kind::Spring6Dof && !data.header.is_v2_1() {
+                return Err(Error::invalid_data("joint type"));
+            }
+            data.write_u8(match joint.kind {
+                joint::Kind::Spring6Dof => 0,
+                joint::Kind::SixDof => 1,
+                joint::Kind::P2P => 2,
+                joint::Kind::ConeTwist => 3,
+                joint::Kind::Slider => 4,
+                joint::Kind::Hinge => 5,
+            })?;
+            data.write_rigid_index(joint.rigids[0])?;
+            data.write_rigid_index(joint.rigids[1])?;
+            data.write_vec(&joint.position)?;
+            data.write_vec(&joint.rotation)?;
+            data.write_vec(&joint.limit_translation.lower)?;
+            data.write_vec(&joint.limit_translation.upper)?;
+            data.write_vec(&joint.limit_rotation.lower)?;
+            data.write_vec(&joint.limit_rotation.upper)?;
+            data.write_vec(&joint.spring_translation)?;
+            data.write_vec(&joint.spring_rotation)?;
+        }
+        self.end(Stage::Joints);
+        Ok(())
+    }
+
+    pub fn write_soft_bodies(&mut self, soft_bodies: &[SoftBody]) -> Result<(), Error> {
+        if !self.header.is_v2_1() {
+            return Err(Error::invalid_data("soft body"));
+        }
+        let mut data = self.begin(Stage::SoftBodies)?;
+        data.write_len(soft_bodies.len())?;
+        for soft_body in soft_bodies {
+            data.write_string(&soft_body.name)?;
+            data.write_string(&soft_body.name_en)?;
+            data.write_u8(match soft_body.shape {
+                soft_body::Shape::TriMesh => 0,
+                soft_body::Shape::Rope => 1,
+            })?;
+            data.write_material_index(soft_body.material)?;
+            data.write_u8(soft_body.group)?;
+            data.write_u16(soft_body.non_collision_groups)?;
+            let mut flags = 0;
+            if soft_body.b_link {
+                flags |= 0x01;
+            }
+            if soft_body.generate_clusters {
+                flags |= 0x02;
+            }
+            if soft_body.link_crossing {
+                flags |= 0x04;
+            }
+            data.write_u8(flags)?;
+            data.write_i32(soft_body.b_link_distance)?;
+            data.write_i32(soft_body.cluster_count)?;
+            data.write_f32(soft_body.total_mass)?;
+            data.write_f32(soft_body.margin)?;
+            data.write_i32(match soft_body.aero_model {
+                soft_body::AeroModel::VPoint => 0,
+                soft_body::AeroModel::VTwoSided => 1,
+                soft_body::AeroModel::VOneSided => 2,
+                soft_body::AeroModel::FTwoSided => 3,
+                soft_body::AeroModel::FOneSided => 4,
+            })?;
+            let config = &soft_body.config;
+            data.write_vec(&[
+                config.vcf, config.dp, config.dg, config.lf, config.pr, config.vc, config.df,
+                config.mt, config.chr, config.khr, config.shr, config.ahr,
+            ])?;
+            let cluster = &soft_body.cluster;
+            data.write_vec(&[
+                cluster.srhr_cl,
+                cluster.skhr_cl,
+                cluster.sshr_cl,
+                cluster.sr_splt_cl,
+                cluster.sk_splt_cl,
+                cluster.ss_splt_cl,
+            ])?;
+            let iteration = &soft_body.iteration;
+            data.write_i32(iteration.v_it)?;
+            data.write_i32(iteration.p_it)?;
+            data.write_i32(iteration.d_it)?;
+            data.write_i32(iteration.c_it)?;
+            let material = &soft_body.material_params;
+            data.write_vec(&[material.lst, material.ast, material.vst])?;
+            data.write_len(soft_body.anchors.len())?;
+            for anchor in &soft_body.anchors {
+                data.write_rigid_index(anchor.rigid)?;
+                data.write_vertex_index(anchor.vertex)?;
+                data.write_u8(anchor.near_mode as u8)?;
+            }
+            data.write_len(soft_body.pins.len())?;
+            for &pin in &soft_body.pins {
+                data.write_vertex_index(pin)?;
+            }
+        }
+        self.end(Stage::SoftBodies);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.stage != Stage::End {
+            return Err(Error::invalid_data("section order"));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const DATA: &[u8] = include_bytes!("../assets/Alicia/Alicia_solid.pmx");
+
+    fn write_all(reader: &Reader) -> Result<Vec<u8>, Error> {
+        let mut writer = Writer::new(vec![], reader.header().clone())?;
+        writer.write_info(
+            &reader.name()?,
+            &reader.name_en()?,
+            &reader.comment()?,
+            &reader.comment_en()?,
+        )?;
+        writer.write_vertices(&reader.vertices().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_faces(&reader.faces().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_textures(&reader.textures().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_materials(&reader.materials().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_bones(&reader.bones().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_morphs(&reader.morphs().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_display_groups(&reader.display_groups().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_rigids(&reader.rigids().collect::<Result<Vec<_>, _>>()?)?;
+        writer.write_joints(&reader.joints().collect::<Result<Vec<_>, _>>()?)?;
+        writer.finish()
+    }
+
+    #[test]
+    fn round_trip() {
+        let reader = Reader::new(Cursor::new(DATA)).unwrap();
+        let data = write_all(&reader).unwrap();
+        assert!(data == DATA);
+    }
+
+    #[test]
+    fn utf8() {
+        let reader = Reader::new(Cursor::new(DATA)).unwrap();
+        let mut writer = Writer::new(
+            vec![],
+            Header {
+                encoding: Encoding::Utf8,
+                ..reader.header().clone()
+            },
+        )
+        .unwrap();
+        let name = reader.name().unwrap();
+        writer.write_info(&name, "", "", "").unwrap();
+        let data = &writer.writer[17..];
+        assert!(data[0..4] == (name.len() as u32).to_le_bytes());
+        assert!(&data[4..4 + name.len()] == name.as_bytes());
+    }
+
+    #[test]
+    fn section_order() {
+        let reader = Reader::new(Cursor::new(DATA)).unwrap();
+        let mut writer = Writer::new(vec![], reader.header().clone()).unwrap();
+        assert!(writer.write_faces(&[]).is_err());
+        assert!(writer.write_info("", "", "", "").is_ok());
+        assert!(writer.write_info("", "", "", "").is_err());
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn index_size() {
+        let reader = Reader::new(Cursor::new(DATA)).unwrap();
+        let header = Header {
+            bone_index_size: 1,
+            ..reader.header().clone()
+        };
+        let mut writer = Writer::new(vec![], header).unwrap();
+        writer.write_info("", "", "", "").unwrap();
+        let vertices = reader.vertices().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(writer.write_vertices(&vertices).is_err());
+    }
+}